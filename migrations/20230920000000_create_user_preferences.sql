@@ -0,0 +1,22 @@
+CREATE TABLE user_preferences
+(
+    user_id             INTEGER PRIMARY KEY REFERENCES users (id),
+    min_energy          REAL,
+    max_energy          REAL,
+    target_energy       REAL,
+    min_popularity      INTEGER,
+    max_popularity      INTEGER,
+    target_popularity   INTEGER,
+    min_valence         REAL,
+    max_valence         REAL,
+    target_valence      REAL,
+    min_danceability    REAL,
+    max_danceability    REAL,
+    target_danceability REAL,
+    min_tempo           REAL,
+    max_tempo           REAL,
+    target_tempo        REAL,
+    min_acousticness    REAL,
+    max_acousticness    REAL,
+    target_acousticness REAL
+);
@@ -0,0 +1,8 @@
+CREATE TABLE song_feedback
+(
+    user_id    INTEGER     NOT NULL REFERENCES users (id),
+    song_id    INTEGER     NOT NULL REFERENCES songs (id),
+    kind       TEXT        NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, song_id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE daily_playlists
+(
+    user_id     INTEGER NOT NULL REFERENCES users (id),
+    day         DATE    NOT NULL,
+    playlist_id TEXT    NOT NULL,
+    PRIMARY KEY (user_id, day)
+);
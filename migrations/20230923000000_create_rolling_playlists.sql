@@ -0,0 +1,7 @@
+CREATE TABLE rolling_playlists
+(
+    user_id     INTEGER NOT NULL REFERENCES users (id),
+    period      TEXT    NOT NULL,
+    playlist_id TEXT    NOT NULL,
+    PRIMARY KEY (user_id, period)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE songs
+    ADD COLUMN tempo        REAL,
+    ADD COLUMN key          INTEGER,
+    ADD COLUMN energy       REAL,
+    ADD COLUMN valence      REAL,
+    ADD COLUMN danceability REAL,
+    ADD COLUMN duration_ms  INTEGER;
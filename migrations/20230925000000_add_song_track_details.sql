@@ -0,0 +1,15 @@
+ALTER TABLE songs
+    ADD COLUMN spotify_id   TEXT,
+    ADD COLUMN spotify_uri  TEXT,
+    ADD COLUMN album        TEXT,
+    ADD COLUMN release_date TEXT,
+    ADD COLUMN artists      TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN artist_ids   TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN preview_url  TEXT;
+
+UPDATE songs
+SET spotify_id  = substring(link from '/track/([A-Za-z0-9]+)'),
+    spotify_uri = 'spotify:track:' || substring(link from '/track/([A-Za-z0-9]+)'),
+    artists     = ARRAY [artist];
+
+CREATE INDEX songs_user_id_spotify_id_idx ON songs (user_id, spotify_id);
@@ -0,0 +1,5 @@
+ALTER TABLE users
+    ADD COLUMN spotify_id   TEXT UNIQUE,
+    ADD COLUMN display_name TEXT,
+    ADD COLUMN country      TEXT,
+    ADD COLUMN product      TEXT;
@@ -0,0 +1,14 @@
+ALTER TABLE genres
+    ALTER COLUMN name TYPE TEXT USING name::TEXT,
+    ADD COLUMN display_name TEXT NOT NULL DEFAULT '',
+    ADD COLUMN description  TEXT;
+
+UPDATE genres
+SET display_name = initcap(replace(name, '-', ' '))
+WHERE display_name = '';
+
+ALTER TABLE user_genres
+    ALTER COLUMN genre_id TYPE TEXT USING genre_id::TEXT;
+
+ALTER TABLE songs
+    ALTER COLUMN genre TYPE TEXT USING genre::TEXT;
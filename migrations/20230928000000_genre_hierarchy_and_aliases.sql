@@ -0,0 +1,8 @@
+ALTER TABLE genres
+    ADD COLUMN parent TEXT REFERENCES genres (name);
+
+CREATE TABLE genre_aliases
+(
+    alias TEXT PRIMARY KEY,
+    genre TEXT NOT NULL REFERENCES genres (name)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE user_genres
+    ADD COLUMN weight INTEGER NOT NULL DEFAULT 1 CHECK (weight > 0);
+
+ALTER TABLE user_preferences
+    ADD COLUMN songs_per_day INTEGER;
@@ -0,0 +1,13 @@
+DELETE
+FROM user_genres a
+    USING user_genres b
+WHERE a.ctid < b.ctid
+  AND a.user_id = b.user_id
+  AND a.genre_id = b.genre_id;
+
+DELETE
+FROM user_genres
+WHERE genre_id NOT IN (SELECT name FROM genres);
+
+ALTER TABLE user_genres
+    ADD CONSTRAINT user_genres_user_id_genre_id_key UNIQUE (user_id, genre_id);
@@ -0,0 +1,33 @@
+CREATE TABLE artist_infos
+(
+    id          SERIAL PRIMARY KEY,
+    name        TEXT        NOT NULL UNIQUE,
+    url         TEXT,
+    image       TEXT,
+    bio_summary TEXT,
+    bio_content TEXT,
+    listeners   BIGINT,
+    playcount   BIGINT,
+    tags        TEXT[]      NOT NULL DEFAULT '{}',
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE album_infos
+(
+    id           SERIAL PRIMARY KEY,
+    artist       TEXT        NOT NULL,
+    name         TEXT        NOT NULL,
+    url          TEXT,
+    image        TEXT,
+    wiki_summary TEXT,
+    wiki_content TEXT,
+    listeners    BIGINT,
+    playcount    BIGINT,
+    tags         TEXT[]      NOT NULL DEFAULT '{}',
+    updated_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (artist, name)
+);
+
+ALTER TABLE songs
+    ADD COLUMN artist_info_id INTEGER REFERENCES artist_infos (id),
+    ADD COLUMN album_info_id  INTEGER REFERENCES album_infos (id);
@@ -0,0 +1,12 @@
+ALTER TABLE users
+    ADD COLUMN lastfm_username    TEXT,
+    ADD COLUMN lastfm_imported_at TIMESTAMPTZ;
+
+CREATE TABLE known_tracks
+(
+    user_id        INTEGER     NOT NULL REFERENCES users (id),
+    artist         TEXT        NOT NULL,
+    title          TEXT        NOT NULL,
+    last_played_at TIMESTAMPTZ,
+    PRIMARY KEY (user_id, artist, title)
+);
@@ -0,0 +1,2 @@
+ALTER TABLE user_preferences
+    ADD COLUMN recommendation_source TEXT CHECK (recommendation_source IN ('spotify', 'lastfm'));
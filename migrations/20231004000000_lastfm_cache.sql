@@ -0,0 +1,10 @@
+CREATE TABLE lastfm_cache
+(
+    kind       TEXT        NOT NULL,
+    artist     TEXT        NOT NULL,
+    name       TEXT        NOT NULL DEFAULT '',
+    -- The raw Last.fm response, NULL when Last.fm did not know the entry.
+    body       TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (kind, artist, name)
+);
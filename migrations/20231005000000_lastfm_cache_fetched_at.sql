@@ -0,0 +1,2 @@
+-- Expired entries are purged by age.
+CREATE INDEX lastfm_cache_fetched_at ON lastfm_cache (fetched_at);
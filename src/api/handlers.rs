@@ -3,124 +3,212 @@ use poem::Result;
 use poem::session::Session;
 use poem::web::{Data};
 use poem_openapi::OpenApi;
-use poem_openapi::param::Query;
-use poem_openapi::payload::{Json};
+use poem_openapi::param::{Path, Query};
+use poem_openapi::payload::{Attachment, AttachmentType, Json};
 
-use crate::models::errors::ResponseError;
-use crate::models::genres::{GenreResponse, GenresPayload};
-use crate::models::song::{Song, SongsResponse};
+use crate::models::errors::{GenresError, ResponseError};
+use crate::models::export::{ExportFormat, ExportResponse};
+use crate::models::feedback::{Feedback, FeedbackPayload, FeedbackResponse};
+use crate::models::genres::{allocate, GenreCatalogResponse, GenreResponse, GenresPayload, normalize, spread, UserGenre};
+use crate::models::playlist::RollingPeriod;
+use crate::models::preferences::{Preferences, PreferencesResponse, RecommendationSource};
+use crate::models::song::{Song, SongDetail, SongResponse, SongsResponse};
 use crate::models::spotify::{CodePayload, SpotifyResponse};
+use crate::models::track::{Exclusions, Seeds, SeedStrategy, Track};
+use crate::models::user::{LastFmPayload, UserExport, UserExportResponse, UserSummary};
 use crate::services::db::DB;
-use crate::services::lastfm::LastFM;
-use crate::services::spotify::Spotify;
-use crate::token::token::{read_token, write_token};
+use crate::services::{discovery, export, history};
+use crate::services::lastfm::{LastFM, LastFmError};
+use crate::services::provider::{MAX_CANDIDATE_ATTEMPTS, MusicProvider, Provider, ProviderError, ProviderKind};
+use crate::token::token::load_token;
+
+/// How many top artists and tracks are fetched for the personal seed strategy.
+const PERSONAL_SEED_POOL_SIZE: u32 = 10;
+/// Spotify accepts five seeds in total, so two artists and two tracks leave room for the genre.
+const PERSONAL_SEEDS_PER_GENRE: usize = 2;
+/// How many liked tracks are used as seeds for each genre.
+const LIKED_SEEDS_PER_GENRE: usize = 1;
+/// Spotify rejects playlist requests with more items than this.
+const MAX_PLAYLIST_ITEMS_PER_REQUEST: usize = 100;
 
 pub struct Api;
 
 #[OpenApi]
 impl Api {
-    #[oai(path = "/spotify/exchange", method = "post")]
-    async fn exchange_token(&self, code: Json<CodePayload>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
-        let spotify = Spotify::from_code(code.0.code).await.map_err(|e| poem::error::BadRequest(e))?;
-        let token = match spotify.client.token.clone().lock().await {
-            Ok(token) => match token.clone() {
-                Some(token) => token,
-                None => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: "could not get the token from the mutex guard".to_string() }))),
-            },
-            Err(_) => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: "could not get the mutex lock".to_string() }))),
-        };
+    #[oai(path = "/spotify/login", method = "get")]
+    async fn login(&self, provider: Data<&ProviderKind>, session: &Session) -> Result<SpotifyResponse> {
+        let request = provider.0.authorize_url()?;
+        session.set("oauth_state", request.state);
+        session.set("oauth_verifier", request.verifier);
 
-        match write_token(token.clone(), session) {
-            Ok(_) => {}
-            Err(e) => {
-                return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: e.to_string() })));
-            }
+        Ok(SpotifyResponse::SpotifyResponse(Json(request.url)))
+    }
+
+    #[oai(path = "/spotify/callback", method = "get")]
+    async fn login_callback(&self, code: Query<Option<String>>, state: Query<Option<String>>, error: Query<Option<String>>, provider: Data<&ProviderKind>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
+        if let Some(error) = error.0 {
+            return Ok(SpotifyResponse::BadRequest(Json(ResponseError { message: error })));
         }
 
-        let expires_in = token.expires_in.num_seconds();
-        let user = db.0.insert_user(&token.access_token, expires_in as i32, token.expires_at, token.refresh_token).await.map_err(|e| poem::error::BadRequest(e))?;
-        session.set("user_id", user.id);
+        let expected_state: Option<String> = session.get("oauth_state");
+        let verifier: Option<String> = session.get("oauth_verifier");
+        session.remove("oauth_state");
+        session.remove("oauth_verifier");
 
-        Ok(SpotifyResponse::SpotifyResponse(Json("success".to_string())))
+        let (code, verifier) = match (code.0, verifier) {
+            (Some(code), Some(verifier)) if expected_state.is_some() && state.0 == expected_state => (code, verifier),
+            _ => return Ok(SpotifyResponse::BadRequest(Json(ResponseError { message: "invalid or expired login state".to_string() }))),
+        };
+
+        let provider = provider.0.login_with_pkce(&code, verifier).await.map_err(|e| poem::error::BadRequest(e))?;
+        return complete_login(&provider, db.0, session).await;
     }
 
-    #[oai(path = "/songs", method = "post")]
-    async fn get_daily_songs(&self, lastfm: Data<&LastFM>, db: Data<&DB>, session: &Session) -> Result<SongsResponse> {
-        let token = match read_token(session) {
-            Ok(token) => token,
-            Err(e) => return Ok(SongsResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
-        };
-        let spotify = Spotify::from_token(token.clone(), session).await.map_err(|e| poem::error::NotAcceptable(e))?;
+    #[oai(path = "/spotify/exchange", method = "post")]
+    async fn exchange_token(&self, code: Json<CodePayload>, provider: Data<&ProviderKind>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
+        let provider = provider.0.login_with_code(code.0.code).await.map_err(|e| poem::error::BadRequest(e))?;
+        return complete_login(&provider, db.0, session).await;
+    }
 
-        // update the token on the database
+    #[oai(path = "/songs", method = "post")]
+    async fn get_daily_songs(&self, strategy: Query<Option<SeedStrategy>>, provider: Data<&ProviderKind>, lastfm: Data<&LastFM>, db: Data<&DB>, session: &Session) -> Result<SongsResponse> {
         let user_id = match session.get("user_id") {
             Some(user_id) => user_id,
             None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() }))),
         };
-
-        db.0.update_user_token(user_id, &token.access_token, token.expires_in.num_seconds() as i32, &token.expires_at.unwrap(), &token.refresh_token.unwrap()).await.map_err(|e| poem::error::BadRequest(e))?;
+        let token = match load_token(db.0, provider.0, user_id).await {
+            Ok(token) => token,
+            Err(e) => return Ok(SongsResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
+        };
+        let provider = provider.0.with_token(token);
 
         let previous_user_songs = db.0.get_all_songs_from_user(user_id).await.map_err(|e| poem::error::BadRequest(e))?;
-        let genres = db.0.get_user_genres(1).await.map_err(|e| poem::error::BadRequest(e))?;
+        let disliked_artists = db.0.get_disliked_artists(user_id).await.map_err(|e| poem::error::BadRequest(e))?;
+        let liked_songs = db.0.get_liked_songs(user_id).await.map_err(|e| poem::error::BadRequest(e))?;
+        let liked_seeds = Seeds {
+            artists: vec![],
+            tracks: liked_songs.iter().flat_map(|song| song.track_id()).map(|id| id.to_string()).collect(),
+        };
+        let known_tracks = db.0.get_known_tracks(user_id).await.map_err(|e| poem::error::BadRequest(e))?;
+        let history = known_tracks.into_iter().map(|track| (track.artist, track.title)).collect();
+        let mut exclusions = Exclusions { songs: previous_user_songs, artists: disliked_artists, history };
+        let genres = db.0.get_user_genres(user_id).await.map_err(|e| poem::error::BadRequest(e))?;
+        let preferences = db.0.get_user_preferences(user_id).await.map_err(|e| poem::error::BadRequest(e))?.unwrap_or_default();
+        let top_seeds = match strategy.0.unwrap_or_default() {
+            SeedStrategy::Genre => Seeds::default(),
+            // Tokens from before the user-top-read scope can't read top items, those users get genre seeds.
+            SeedStrategy::Personal => match provider.top_seeds(PERSONAL_SEED_POOL_SIZE).await {
+                Ok(seeds) => seeds,
+                Err(e @ ProviderError::QuotaExhausted { .. }) => return Err(e.into()),
+                Err(err) => {
+                    println!("Failed to get top seeds, falling back to genre seeds: {}", err);
+                    Seeds::default()
+                }
+            },
+        };
+        let budget = preferences.songs_per_day.map(|songs| songs as usize).unwrap_or(genres.len());
+        let weights: Vec<i32> = genres.iter().map(|genre| genre.weight).collect();
+        let slots: Vec<&UserGenre> = genres.iter()
+            .zip(allocate(&weights, budget))
+            .flat_map(|(genre, count)| std::iter::repeat(genre).take(count))
+            .collect();
+        let source = preferences.recommendation_source.unwrap_or_default();
         let mut songs: Vec<Song> = vec![];
-        for genre in genres {
-            let genre = genre.name.into();
-            let spotify_track = match spotify.generate_daily_song(&genre).await {
-                Some(track) => track,
-                None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no track found".to_string() }))),
+        // Fixed before the loop, the songs saved below would otherwise advance the offset twice per slot.
+        let base_offset = exclusions.songs.len();
+        for (index, genre) in slots.into_iter().enumerate() {
+            let children = db.0.get_child_genres(&genre.name).await.map_err(|e| poem::error::BadRequest(e))?;
+            let offset = base_offset + index;
+            let genre = spread(&genre.name, &children, offset);
+            let seeds = top_seeds.rotate(index, PERSONAL_SEEDS_PER_GENRE)
+                .with_tracks(liked_seeds.rotate(index, LIKED_SEEDS_PER_GENRE).tracks);
+            let liked_song = liked_songs.iter().cycle().nth(index);
+            // Each source falls back to the other when it has nothing new for the genre.
+            let generated = match source {
+                RecommendationSource::Spotify => match provider.generate_daily_song(&genre, &seeds, &preferences, &exclusions).await {
+                    Ok(None) => discovery::daily_song(lastfm.0, &provider, &genre, liked_song, &exclusions, offset).await,
+                    generated => generated,
+                },
+                RecommendationSource::LastFm => match discovery::daily_song(lastfm.0, &provider, &genre, liked_song, &exclusions, offset).await {
+                    Ok(None) => provider.generate_daily_song(&genre, &seeds, &preferences, &exclusions).await,
+                    generated => generated,
+                },
             };
-
-            // This can make an infinite loop, but I am okay with that for now ::)
-            let mut song_exists = previous_user_songs.iter().any(|song| song.title == spotify_track.name && song.artist == spotify_track.artists.first().unwrap().name);
-            while song_exists {
-                let spotify_track = match spotify.generate_daily_song(&genre).await {
-                    Some(track) => track,
-                    None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no track found".to_string() }))),
-                };
-                song_exists = previous_user_songs.iter().any(|song| song.title == spotify_track.name && song.artist == spotify_track.artists.first().unwrap().name);
-            }
-
-            let artist_name = match &spotify_track.artists.first() {
-                Some(artist) => &artist.name,
-                None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no artist found".to_string() }))),
-            };
-
-            let song_name = &spotify_track.name;
-            let link = match spotify_track.external_urls.get("spotify") {
-                Some(link) => link,
-                None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no link found".to_string() }))),
+            let track = match generated {
+                Ok(Some(track)) => track,
+                Ok(None) => {
+                    let message = format!("no new {} track found after {} attempts", genre, MAX_CANDIDATE_ATTEMPTS);
+                    return Ok(SongsResponse::NotFound(Json(ResponseError { message })));
+                }
+                Err(e @ ProviderError::QuotaExhausted { .. }) => return Err(e.into()),
+                Err(e) => return Ok(SongsResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
             };
 
-            let albums = match spotify.search_track_details(artist_name, song_name).await {
-                Some(albums) => match albums.first() {
-                    Some(album) => album.to_owned(),
-                    None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no album found 1".to_string() }))),
+            let lastfm_track = match lastfm.0.get_details(&track.artist, &track.name).await {
+                Ok(track) => Some(track),
+                Err(err) => {
+                    println!("Failed to get Last.fm details: {}", err);
+                    None
                 }
-                None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no album found 2".to_string() }))),
             };
 
-            let album_cover = match albums.images.first() {
-                Some(image) => &image.url,
-                None => return Ok(SongsResponse::NotFound(Json(ResponseError { message: "no album cover found 3".to_string() }))),
-            };
+            let description = lastfm_track.as_ref().and_then(|details| details.track_description.as_deref());
+            let summary = lastfm_track.as_ref().and_then(|details| details.track_summary.as_deref());
 
-            let lastfm_track = match lastfm.0.get_details(artist_name, song_name).await {
-                Ok(track) => track,
-                Err(e) => return Ok(SongsResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
+            let features = match provider.audio_features(&track.id).await {
+                Ok(features) => Some(features),
+                Err(err) => {
+                    println!("Failed to get audio features: {:?}", err);
+                    None
+                }
             };
 
-            let description = &lastfm_track.track_description;
-            let summary = &lastfm_track.track_summary;
-
-            let song = match db.0.save_song(song_name, artist_name, link, description, summary, &genre, album_cover).await {
+            let song = match db.0.save_song(user_id, &track, description, summary, &genre, features.as_ref()).await {
                 Ok(song) => song,
                 Err(e) => return Ok(SongsResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
             };
-            songs.push(song);
+            if let Err(err) = enrich_song(lastfm.0, db.0, song.id, &track).await {
+                println!("Failed to store artist and album info: {:?}", err);
+            }
+            songs.push(song.clone());
+            exclusions.songs.push(song);
+        }
+
+        if let Err(err) = sync_rolling_playlists(&provider, db.0, user_id).await {
+            println!("Failed to sync rolling playlists: {:?}", err);
         }
         return Ok(SongsResponse::Song(Json(songs)));
     }
 
+    #[oai(path = "/songs/:id", method = "get")]
+    async fn get_song(&self, id: Path<i32>, db: Data<&DB>, session: &Session) -> Result<SongResponse> {
+        let user_id = session.get("user_id").ok_or(SongResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let not_found = |e: sqlx::Error| SongResponse::NotFound(Json(ResponseError { message: e.to_string() }));
+
+        let song = match db.0.get_song(user_id, id.0).await.map_err(not_found)? {
+            Some(song) => song,
+            None => return Ok(SongResponse::NotFound(Json(ResponseError { message: "no song found".to_string() }))),
+        };
+        let detail = SongDetail {
+            artist_info: db.0.get_song_artist_info(song.id).await.map_err(not_found)?,
+            album_info: db.0.get_song_album_info(song.id).await.map_err(not_found)?,
+            song,
+        };
+
+        return Ok(SongResponse::Song(Json(detail)));
+    }
+
+    #[oai(path = "/songs/:id/feedback", method = "post")]
+    async fn save_feedback(&self, id: Path<i32>, feedback: Json<FeedbackPayload>, db: Data<&DB>, session: &Session) -> Result<FeedbackResponse> {
+        let user_id = session.get("user_id").ok_or(FeedbackResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let saved = db.0.save_feedback(user_id, id.0, &feedback.0.kind).await.map_err(|e| FeedbackResponse::BadRequest(Json(ResponseError { message: e.to_string() })))?;
+        if !saved {
+            return Ok(FeedbackResponse::NotFound(Json(ResponseError { message: "no song found".to_string() })));
+        }
+
+        return Ok(FeedbackResponse::Feedback(Json(Feedback { song_id: id.0, kind: feedback.0.kind })));
+    }
+
     #[oai(path = "/songs", method = "get")]
     async fn get_songs(&self, day: Query<Option<String>>, db: Data<&DB>, session: &Session) -> Result<SongsResponse> {
         return match day.0 {
@@ -136,43 +224,286 @@ impl Api {
         };
     }
 
-    #[oai(path = "/genres", method = "post")]
-    async fn save_genres(&self, genres: Json<GenresPayload>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
-        let user_id = session.get("user_id").ok_or(SpotifyResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
-        let genres = genres.0.genres.iter().map(|genre| genre.clone().into()).collect();
-        let result = match db.0.insert_user_genres(user_id, genres).await {
-            Ok(_) => "success".to_string(),
-            Err(e) => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
+    #[oai(path = "/songs/export", method = "get")]
+    async fn export_songs(&self, format: Query<ExportFormat>, from: Query<Option<String>>, to: Query<Option<String>>, db: Data<&DB>, session: &Session) -> Result<ExportResponse> {
+        let user_id = session.get("user_id").ok_or(ExportResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let from = match from.0 {
+            Some(from) => NaiveDate::parse_from_str(&from, "%Y-%m-%d").map_err(|e| ExportResponse::BadRequest(Json(ResponseError { message: e.to_string() })))?,
+            None => NaiveDate::from_ymd_opt(1970, 1, 1).unwrap_or_default(),
         };
-        return Ok(SpotifyResponse::SpotifyResponse(Json(result)));
+        let to = match to.0 {
+            Some(to) => NaiveDate::parse_from_str(&to, "%Y-%m-%d").map_err(|e| ExportResponse::BadRequest(Json(ResponseError { message: e.to_string() })))?,
+            None => chrono::Utc::now().naive_utc().date(),
+        };
+        if from > to {
+            return Ok(ExportResponse::BadRequest(Json(ResponseError { message: "from must not be after to".to_string() })));
+        }
+
+        let songs = db.0.get_songs_between(user_id, &from, &to).await.map_err(|e| ExportResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        let body = export::render(&format.0, &songs);
+        let attachment = Attachment::new(body.into_bytes())
+            .attachment_type(AttachmentType::Attachment)
+            .filename(format!("musicapp-songs-{}-{}.{}", from, to, format.0.extension()));
+
+        return Ok(ExportResponse::Export(attachment));
+    }
+
+    #[oai(path = "/genres", method = "post")]
+    async fn save_genres(&self, genres: Json<GenresPayload>, db: Data<&DB>, session: &Session) -> Result<GenreResponse> {
+        let user_id = session.get("user_id").ok_or(GenreResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let genres = resolve_genre_payload(db.0, &genres.0).await?;
+        db.0.insert_user_genres(user_id, genres).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        let genres = db.0.get_user_genres(user_id).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(GenreResponse::GenreResponse(Json(genres)));
+    }
+
+    #[oai(path = "/genres", method = "put")]
+    async fn replace_genres(&self, genres: Json<GenresPayload>, db: Data<&DB>, session: &Session) -> Result<GenreResponse> {
+        let user_id = session.get("user_id").ok_or(GenreResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let genres = resolve_genre_payload(db.0, &genres.0).await?;
+        db.0.replace_user_genres(user_id, genres).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        let genres = db.0.get_user_genres(user_id).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(GenreResponse::GenreResponse(Json(genres)));
+    }
+
+    #[oai(path = "/genres/:name", method = "delete")]
+    async fn delete_genre(&self, name: Path<String>, db: Data<&DB>, session: &Session) -> Result<GenreResponse> {
+        let user_id = session.get("user_id").ok_or(GenreResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let name = normalize(&name.0);
+        let resolved = db.0.resolve_genres(&[name.clone()]).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        let genre = resolved.into_iter().flat_map(|(_, genre)| genre).next().unwrap_or(name);
+        let deleted = db.0.delete_user_genre(user_id, &genre).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        if !deleted {
+            return Ok(GenreResponse::NotFound(Json(ResponseError { message: format!("genre {} is not saved", genre) })));
+        }
+        let genres = db.0.get_user_genres(user_id).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(GenreResponse::GenreResponse(Json(genres)));
     }
 
     #[oai(path = "/genres", method = "get")]
     async fn get_genres(&self, db: Data<&DB>, session: &Session) -> Result<GenreResponse> {
         let user_id = session.get("user_id").ok_or(GenreResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
         let genres = db.0.get_user_genres(user_id).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
-        let genres = genres.iter().map(|genre| genre.name.into()).collect();
 
         return Ok(GenreResponse::GenreResponse(Json(genres)));
     }
 
+    #[oai(path = "/genres/catalog", method = "get")]
+    async fn get_genre_catalog(&self, db: Data<&DB>) -> Result<GenreCatalogResponse> {
+        let genres = db.0.get_genre_catalog().await.map_err(|e| GenreCatalogResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(GenreCatalogResponse::GenreCatalog(Json(genres)));
+    }
+
     #[oai(path = "/playlist", method = "get")]
-    async fn generate_playlist(&self, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
+    async fn generate_playlist(&self, provider: Data<&ProviderKind>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
         let user_id = session.get("user_id").ok_or(SpotifyResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
         let day: NaiveDate = chrono::Utc::now().naive_utc().date();
         let songs = db.0.get_daily_songs(&day, user_id).await.map_err(|e| SpotifyResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
-        let token = match read_token(session) {
+        let token = match load_token(db.0, provider.0, user_id).await {
             Ok(token) => token,
             Err(e) => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: e.to_string() }))),
         };
 
-        let spotify = Spotify::from_token(token.clone(), session).await.map_err(|e| poem::error::NotAcceptable(e))?;
-        let playlist_id = spotify.create_playlist(&day).await.map_err(|e| poem::error::NotAcceptable(e))?;
-        let mut uris: Vec<String> = vec![];
-        for song in songs {
-            uris.push(song.link);
+        let provider = provider.0.with_token(token);
+        let uris: Vec<String> = songs.iter().flat_map(|song| song.track_id()).map(|id| id.to_string()).collect();
+
+        let playlist_id = db.0.get_daily_playlist(user_id, &day).await.map_err(|e| poem::error::BadRequest(e))?;
+        match playlist_id {
+            Some(playlist_id) => {
+                provider.replace_playlist_items(&playlist_id, uris).await?;
+            }
+            None => {
+                let playlist_id = provider.create_playlist(&format!("MusicApp Day {day}"), &format!("MusicApp playlist for {day}")).await?;
+                db.0.save_daily_playlist(user_id, &day, &playlist_id).await.map_err(|e| poem::error::BadRequest(e))?;
+                provider.add_songs_to_playlist(&playlist_id, uris).await?;
+            }
         }
-        spotify.add_songs_to_playlist(playlist_id, uris).await.map_err(|e| poem::error::NotAcceptable(e))?;
+        sync_rolling_playlists(&provider, db.0, user_id).await?;
         Ok(SpotifyResponse::SpotifyResponse(Json("success".to_string())))
     }
+
+    #[oai(path = "/me", method = "delete")]
+    async fn delete_me(&self, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
+        let user_id = session.get("user_id").ok_or(SpotifyResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        db.0.delete_user(user_id).await.map_err(|e| SpotifyResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        session.purge();
+
+        Ok(SpotifyResponse::SpotifyResponse(Json("success".to_string())))
+    }
+
+    #[oai(path = "/me/lastfm", method = "put")]
+    async fn link_lastfm(&self, payload: Json<LastFmPayload>, lastfm: Data<&LastFM>, db: Data<&DB>, session: &Session) -> Result<SpotifyResponse> {
+        let user_id = session.get("user_id").ok_or(SpotifyResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let username = payload.0.username.trim().to_string();
+        match lastfm.0.recent_tracks(&username, 1, None, None).await {
+            Ok(_) => {}
+            Err(LastFmError::NotFound(_)) => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: format!("no Last.fm user named {}", username) }))),
+            Err(e) => return Ok(SpotifyResponse::BadRequest(Json(ResponseError { message: e.to_string() }))),
+        }
+
+        // Linking the same account again only imports what was scrobbled since the last import.
+        db.0.set_lastfm_username(user_id, &username).await.map_err(|e| poem::error::BadRequest(e))?;
+        tokio::spawn(history::import(lastfm.0.clone(), db.0.clone(), user_id, username));
+
+        return Ok(SpotifyResponse::SpotifyResponse(Json("import started".to_string())));
+    }
+
+    #[oai(path = "/me/export", method = "get")]
+    async fn export_me(&self, db: Data<&DB>, session: &Session) -> Result<UserExportResponse> {
+        let user_id = session.get("user_id").ok_or(UserExportResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let not_found = |e: sqlx::Error| UserExportResponse::NotFound(Json(ResponseError { message: e.to_string() }));
+
+        let user = match db.0.get_user(user_id).await.map_err(not_found)? {
+            Some(user) => user,
+            None => return Ok(UserExportResponse::NotFound(Json(ResponseError { message: "no user found".to_string() }))),
+        };
+        let genres = db.0.get_user_genres(user_id).await.map_err(not_found)?;
+        let export = UserExport {
+            user: UserSummary {
+                id: user.id,
+                spotify_id: user.spotify_id,
+                display_name: user.display_name,
+                country: user.country,
+                product: user.product,
+                token_expires_at: user.expires_at,
+                lastfm_username: user.lastfm_username,
+            },
+            genres,
+            preferences: db.0.get_user_preferences(user_id).await.map_err(not_found)?,
+            songs: db.0.get_all_songs_from_user(user_id).await.map_err(not_found)?,
+            feedback: db.0.get_user_feedback(user_id).await.map_err(not_found)?,
+            playlists: db.0.get_user_playlists(user_id).await.map_err(not_found)?,
+            known_tracks: db.0.get_known_tracks(user_id).await.map_err(not_found)?,
+        };
+
+        return Ok(UserExportResponse::UserExport(Json(export)));
+    }
+
+    #[oai(path = "/preferences", method = "get")]
+    async fn get_preferences(&self, db: Data<&DB>, session: &Session) -> Result<PreferencesResponse> {
+        let user_id = session.get("user_id").ok_or(PreferencesResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        let preferences = db.0.get_user_preferences(user_id).await.map_err(|e| PreferencesResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(PreferencesResponse::Preferences(Json(preferences.unwrap_or_default())));
+    }
+
+    #[oai(path = "/preferences", method = "put")]
+    async fn save_preferences(&self, preferences: Json<Preferences>, db: Data<&DB>, session: &Session) -> Result<PreferencesResponse> {
+        let user_id = session.get("user_id").ok_or(PreferencesResponse::NotFound(Json(ResponseError { message: "no user id found".to_string() })))?;
+        if let Err(message) = preferences.0.validate() {
+            return Ok(PreferencesResponse::BadRequest(Json(ResponseError { message })));
+        }
+        let preferences = db.0.upsert_user_preferences(user_id, &preferences.0).await.map_err(|e| PreferencesResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+
+        return Ok(PreferencesResponse::Preferences(Json(preferences)));
+    }
+}
+
+/// Creates the user's rolling playlists if needed and replaces their items with the songs in each window.
+async fn sync_rolling_playlists(provider: &Provider, db: &DB, user_id: i32) -> Result<()> {
+    let today: NaiveDate = chrono::Utc::now().naive_utc().date();
+    for period in RollingPeriod::ALL {
+        let songs = db.get_songs_between(user_id, &period.start(&today), &today).await.map_err(|e| poem::error::BadRequest(e))?;
+        let uris: Vec<String> = songs.iter().flat_map(|song| song.track_id()).map(|id| id.to_string()).collect();
+        let mut chunks = uris.chunks(MAX_PLAYLIST_ITEMS_PER_REQUEST);
+
+        let playlist_id = match db.get_rolling_playlist(user_id, &period).await.map_err(|e| poem::error::BadRequest(e))? {
+            Some(playlist_id) => {
+                let first = chunks.next().map(|chunk| chunk.to_vec()).unwrap_or_default();
+                provider.replace_playlist_items(&playlist_id, first).await?;
+                playlist_id
+            }
+            None => {
+                let playlist_id = provider.create_playlist(period.name(), period.description()).await?;
+                db.save_rolling_playlist(user_id, &period, &playlist_id).await.map_err(|e| poem::error::BadRequest(e))?;
+                playlist_id
+            }
+        };
+        for chunk in chunks {
+            provider.add_songs_to_playlist(&playlist_id, chunk.to_vec()).await?;
+        }
+    }
+
+    return Ok(());
+}
+
+/// Resolves payload names to catalog genres with their weights, rejecting the payload
+/// with the list of valid genres when any name is unknown.
+async fn resolve_genre_payload(db: &DB, payload: &GenresPayload) -> Result<Vec<(String, i32)>> {
+    if payload.genres.iter().any(|genre| genre.weight.is_some_and(|weight| weight < 1)) {
+        let message = "genre weights must be at least 1".to_string();
+        return Err(GenreResponse::BadRequest(Json(GenresError { message, unknown: vec![], valid: vec![] })).into());
+    }
+
+    let names: Vec<String> = payload.genres.iter().map(|genre| normalize(&genre.genre)).collect();
+    let resolved = db.resolve_genres(&names).await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+    let unknown: Vec<String> = resolved.iter()
+        .zip(payload.genres.iter())
+        .filter(|((_, genre), _)| genre.is_none())
+        .map(|(_, payload)| payload.genre.clone())
+        .collect();
+    if !unknown.is_empty() {
+        let catalog = db.get_genre_catalog().await.map_err(|e| GenreResponse::NotFound(Json(ResponseError { message: e.to_string() })))?;
+        let message = format!("unknown genres: {}", unknown.join(", "));
+        let valid = catalog.into_iter().map(|genre| genre.name).collect();
+        return Err(GenreResponse::BadRequest(Json(GenresError { message, unknown, valid })).into());
+    }
+
+    let genres = resolved.into_iter()
+        .zip(payload.genres.iter())
+        .flat_map(|((_, genre), payload)| genre.map(|genre| (genre, payload.weight.unwrap_or(1))))
+        .collect();
+    return Ok(genres);
+}
+
+/// Stores Last.fm artist and album context for a saved song, an artist or album Last.fm
+/// does not know just leaves that part of the detail card empty.
+async fn enrich_song(lastfm: &LastFM, db: &DB, song_id: i32, track: &Track) -> Result<(), sqlx::Error> {
+    let artist_info_id = match lastfm.artist_info(&track.artist).await {
+        Ok(artist) => Some(db.upsert_artist_info(&artist.into()).await?),
+        Err(LastFmError::NotFound(_)) => None,
+        Err(err) => {
+            println!("Failed to get Last.fm artist info: {}", err);
+            None
+        }
+    };
+
+    let album_info_id = match &track.album {
+        Some(album) => match lastfm.album_info(&track.artist, album).await {
+            Ok(album) => Some(db.upsert_album_info(&album.into()).await?),
+            Err(LastFmError::NotFound(_)) => None,
+            Err(err) => {
+                println!("Failed to get Last.fm album info: {}", err);
+                None
+            }
+        },
+        None => None,
+    };
+
+    return db.link_song_infos(song_id, artist_info_id, album_info_id).await;
+}
+
+/// Stores the token the provider obtained for the account's user and binds the user to the session.
+async fn complete_login(provider: &Provider, db: &DB, session: &Session) -> Result<SpotifyResponse> {
+    let token = match provider.token().await {
+        Some(token) => token,
+        None => return Ok(SpotifyResponse::NotFound(Json(ResponseError { message: "could not get the token from the provider".to_string() }))),
+    };
+
+    let account = provider.current_user().await?;
+
+    // Users created before accounts were tracked keep their data by taking over the row of their session.
+    if let Some(user_id) = session.get::<i32>("user_id") {
+        db.claim_user(user_id, &account.id).await.map_err(|e| poem::error::BadRequest(e))?;
+    }
+
+    let expires_in = token.expires_in.num_seconds();
+    let user = db.upsert_user(&account, &token.access_token, expires_in as i32, token.expires_at, token.refresh_token).await.map_err(|e| poem::error::BadRequest(e))?;
+    session.set("user_id", user.id);
+
+    Ok(SpotifyResponse::SpotifyResponse(Json("success".to_string())))
 }
\ No newline at end of file
@@ -4,10 +4,13 @@ use std::env;
 
 use poem::{EndpointExt, listener::TcpListener, Route};
 use poem::session::{CookieConfig, CookieSession};
+use poem::web::cookie::CookieKey;
 use poem_openapi::OpenApiService;
 
+use crate::services::catalog;
 use crate::services::db::DB;
-use crate::services::lastfm::LastFM;
+use crate::services::lastfm::{CacheTtl, DEFAULT_BASE_URL, LastFM};
+use crate::services::provider::ProviderKind;
 
 mod api;
 mod models;
@@ -20,7 +23,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let db = DB::new(env::var("DATABASE_URL").expect("DATABASE_URL must be set")).await?;
 
-    let lastfm = LastFM::new(env::var("LAST_FM_KEY").expect("LAST_FM_KEY must be set")).await?;
+    let lastfm_url = env::var("LAST_FM_URL").unwrap_or(DEFAULT_BASE_URL.to_string());
+    let lastfm = LastFM::new(env::var("LAST_FM_KEY").expect("LAST_FM_KEY must be set"), lastfm_url, db.clone(), CacheTtl::from_env()).await?;
+
+    let provider = ProviderKind::from_env();
+
+    // The session holds the user id that stored tokens are looked up by, so it is encrypted and can't be forged.
+    let session_key = env::var("SESSION_KEY").expect("SESSION_KEY must be set");
+    assert!(session_key.len() >= 32, "SESSION_KEY must be at least 32 bytes");
+
+    match provider.genre_seeds().await {
+        Ok(seeds) => db.insert_genre_seeds(&seeds).await?,
+        Err(err) => println!("Failed to load genre seeds: {}", err),
+    }
+
+    tokio::spawn(catalog::describe(lastfm.clone(), db.clone()));
+    tokio::spawn(lastfm.clone().purge_cache());
+    tokio::spawn(token::worker::run(db.clone(), provider.clone()));
 
     let api_service =
         OpenApiService::new(api::handlers::Api, "Hello World", "1.0").server("http://localhost:3000/api");
@@ -30,7 +49,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let app = Route::new()
         .nest("/api", api_service)
         .nest("/ui", ui)
-        .with(CookieSession::new(CookieConfig::default().secure(false)))
+        .with(CookieSession::new(CookieConfig::private(CookieKey::derive_from(session_key.as_bytes())).secure(false)))
+        .data(provider)
         .data(lastfm)
         .data(db);
 
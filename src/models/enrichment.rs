@@ -0,0 +1,75 @@
+use poem_openapi::Object;
+
+use crate::models::lastfm::{AlbumInfo, ArtistInfo, Image};
+
+/// Artist context from Last.fm shown on a song's detail card.
+#[derive(Object, Clone)]
+pub struct ArtistDetails {
+    pub name: String,
+    pub url: Option<String>,
+    pub image: Option<String>,
+    pub bio_summary: Option<String>,
+    pub bio_content: Option<String>,
+    pub listeners: Option<i64>,
+    pub playcount: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+/// Album context from Last.fm shown on a song's detail card.
+#[derive(Object, Clone)]
+pub struct AlbumDetails {
+    pub artist: String,
+    pub name: String,
+    pub url: Option<String>,
+    pub image: Option<String>,
+    pub wiki_summary: Option<String>,
+    pub wiki_content: Option<String>,
+    pub listeners: Option<i64>,
+    pub playcount: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+impl From<ArtistInfo> for ArtistDetails {
+    fn from(value: ArtistInfo) -> Self {
+        let (listeners, playcount) = match value.stats {
+            Some(stats) => (stats.listeners.and_then(|count| count.parse().ok()), stats.playcount.and_then(|count| count.parse().ok())),
+            None => (None, None),
+        };
+        let (bio_summary, bio_content) = match value.bio {
+            Some(bio) => (bio.summary, bio.content),
+            None => (None, None),
+        };
+
+        return Self {
+            image: Image::largest(&value.image).map(|url| url.to_string()),
+            name: value.name,
+            url: value.url,
+            bio_summary,
+            bio_content,
+            listeners,
+            playcount,
+            tags: value.tags.tag.into_iter().map(|tag| tag.name).collect(),
+        };
+    }
+}
+
+impl From<AlbumInfo> for AlbumDetails {
+    fn from(value: AlbumInfo) -> Self {
+        let (wiki_summary, wiki_content) = match value.wiki {
+            Some(wiki) => (wiki.summary, wiki.content),
+            None => (None, None),
+        };
+
+        return Self {
+            image: Image::largest(&value.image).map(|url| url.to_string()),
+            artist: value.artist,
+            name: value.name,
+            url: value.url,
+            wiki_summary,
+            wiki_content,
+            listeners: value.listeners.and_then(|count| count.parse().ok()),
+            playcount: value.playcount.and_then(|count| count.parse().ok()),
+            tags: value.tags.tag.into_iter().map(|tag| tag.name).collect(),
+        };
+    }
+}
@@ -3,4 +3,13 @@ use poem_openapi::Object;
 #[derive(Object)]
 pub struct ResponseError {
     pub message: String,
-}   
\ No newline at end of file
+}
+
+/// Returned when a genre payload is rejected, `unknown` and `valid` are only filled when it names
+/// genres that are not in the catalog.
+#[derive(Object)]
+pub struct GenresError {
+    pub message: String,
+    pub unknown: Vec<String>,
+    pub valid: Vec<String>,
+}
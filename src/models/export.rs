@@ -0,0 +1,36 @@
+use poem_openapi::{ApiResponse, Enum};
+use poem_openapi::payload::{Attachment, Json};
+
+use crate::models::errors::ResponseError;
+
+#[derive(Enum, Copy, Clone, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum ExportFormat {
+    M3u,
+    Xspf,
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            ExportFormat::M3u => "m3u",
+            ExportFormat::Xspf => "xspf",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        };
+    }
+}
+
+#[derive(ApiResponse)]
+pub enum ExportResponse {
+    #[oai(status = 200)]
+    Export(Attachment<Vec<u8>>),
+
+    #[oai(status = 404)]
+    NotFound(Json<ResponseError>),
+
+    #[oai(status = 400)]
+    BadRequest(Json<ResponseError>),
+}
\ No newline at end of file
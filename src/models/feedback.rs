@@ -0,0 +1,55 @@
+use poem_openapi::{ApiResponse, Enum, Object};
+use poem_openapi::payload::Json;
+
+use crate::models::errors::ResponseError;
+
+#[derive(Enum, Copy, Clone, PartialEq)]
+#[oai(rename_all = "lowercase")]
+pub enum FeedbackKind {
+    Like,
+    Dislike,
+    Skip,
+}
+
+#[derive(Object)]
+pub struct FeedbackPayload {
+    pub kind: FeedbackKind,
+}
+
+#[derive(Object)]
+pub struct Feedback {
+    pub song_id: i32,
+    pub kind: FeedbackKind,
+}
+
+impl From<String> for FeedbackKind {
+    fn from(value: String) -> Self {
+        match value {
+            s if s.eq_ignore_ascii_case("like") => FeedbackKind::Like,
+            s if s.eq_ignore_ascii_case("dislike") => FeedbackKind::Dislike,
+            _ => FeedbackKind::Skip,
+        }
+    }
+}
+
+impl From<&FeedbackKind> for String {
+    fn from(value: &FeedbackKind) -> Self {
+        return match value {
+            FeedbackKind::Like => "like".to_string(),
+            FeedbackKind::Dislike => "dislike".to_string(),
+            FeedbackKind::Skip => "skip".to_string(),
+        };
+    }
+}
+
+#[derive(ApiResponse)]
+pub enum FeedbackResponse {
+    #[oai(status = 200)]
+    Feedback(Json<Feedback>),
+
+    #[oai(status = 404)]
+    NotFound(Json<ResponseError>),
+
+    #[oai(status = 400)]
+    BadRequest(Json<ResponseError>),
+}
\ No newline at end of file
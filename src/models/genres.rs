@@ -1,74 +1,192 @@
-use poem_openapi::{ApiResponse, Enum, Object};
+use poem_openapi::{ApiResponse, Object};
 use poem_openapi::payload::Json;
-use crate::models::errors::ResponseError;
+use crate::models::errors::{GenresError, ResponseError};
 
+/// A genre from the catalog, `name` is the Spotify genre seed.
+#[derive(Object, Clone)]
 pub struct Genre {
-    pub name: GenreTypes,
-}
-
-#[derive(sqlx::Type)]
-#[sqlx(rename_all = "lowercase")]
-#[derive(Enum)]
-#[derive(PartialEq)]
-#[derive(Copy, Clone)]
-pub enum GenreTypes {
-    Unknown,
-    Pop,
-    Rock,
-    Metal,
+    pub name: String,
+    pub display_name: String,
+    pub description: Option<String>,
+    /// Broader genre this one belongs to, e.g. `metal` for `death-metal`.
+    pub parent: Option<String>,
 }
 
 #[derive(Object)]
 pub struct GenrePayload {
     pub genre: String,
+    /// Share of the daily songs relative to the other genres, defaults to 1.
+    pub weight: Option<i32>,
 }
 
 #[derive(Object)]
 pub struct GenresPayload {
-    pub genres: Vec<String>,
+    pub genres: Vec<GenrePayload>,
 }
 
-impl From<String> for GenreTypes {
-    fn from(value: String) -> Self {
-        match value {
-            s if s.eq_ignore_ascii_case("pop") => GenreTypes::Pop,
-            s if s.eq_ignore_ascii_case("rock") => GenreTypes::Rock,
-            s if s.eq_ignore_ascii_case("metal") => GenreTypes::Metal,
-            _ => GenreTypes::Unknown,
-        }
-    }
+/// A genre a user saved, with the weight used to split their daily songs.
+#[derive(Object, Clone)]
+pub struct UserGenre {
+    pub name: String,
+    pub weight: i32,
+}
+
+/// Aliases for seeds that normalizing free-text input alone does not reach.
+pub const DEFAULT_ALIASES: [(&str, &str); 8] = [
+    ("r&b", "r-n-b"),
+    ("rnb", "r-n-b"),
+    ("hiphop", "hip-hop"),
+    ("rap", "hip-hop"),
+    ("dnb", "drum-and-bass"),
+    ("rock-and-roll", "rock-n-roll"),
+    ("alternative-rock", "alt-rock"),
+    ("electronica", "electronic"),
+];
+
+/// Normalizes free-text input such as `Hip Hop` into seed form, `hip-hop`.
+pub fn normalize(input: &str) -> String {
+    return input
+        .trim()
+        .to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-");
 }
 
-impl From<GenreTypes> for String {
-    fn from(value: GenreTypes) -> Self {
-        return match value {
-            GenreTypes::Unknown => "unknown".to_string(),
-            GenreTypes::Pop => "pop".to_string(),
-            GenreTypes::Rock => "rock".to_string(),
-            GenreTypes::Metal => "metal".to_string(),
-        };
+/// Guesses the parent of a seed from its parts, `death-metal` and `metal-misc` both belong to `metal`.
+pub fn parent_of(seed: &str, seeds: &[String]) -> Option<String> {
+    let parts: Vec<&str> = seed.split('-').collect();
+    if parts.len() < 2 {
+        return None;
     }
+
+    let candidates = [parts[parts.len() - 1], parts[0]];
+    return candidates.iter()
+        .find(|candidate| seeds.iter().any(|s| s == *candidate))
+        .map(|candidate| candidate.to_string());
+}
+
+/// Spreads daily songs for a parent genre across itself and its children.
+pub fn spread(genre: &str, children: &[String], offset: usize) -> String {
+    let index = offset % (children.len() + 1);
+    return match index {
+        0 => genre.to_string(),
+        _ => children[index - 1].clone(),
+    };
 }
 
-impl From<&GenreTypes> for String {
-    fn from(value: &GenreTypes) -> Self {
-        return match value {
-            GenreTypes::Unknown => "unknown".to_string(),
-            GenreTypes::Pop => "pop".to_string(),
-            GenreTypes::Rock => "rock".to_string(),
-            GenreTypes::Metal => "metal".to_string(),
-        };
+/// Splits `budget` songs across genres in proportion to `weights` using largest remainder,
+/// so 5 songs weighted 3:1:1 become 3, 1 and 1.
+pub fn allocate(weights: &[i32], budget: usize) -> Vec<usize> {
+    let total: i64 = weights.iter().map(|weight| (*weight).max(0) as i64).sum();
+    if total == 0 {
+        return vec![0; weights.len()];
     }
+
+    let shares: Vec<(usize, i64)> = weights.iter()
+        .map(|weight| {
+            let scaled = (*weight).max(0) as i64 * budget as i64;
+            ((scaled / total) as usize, scaled % total)
+        })
+        .collect();
+    let mut slots: Vec<usize> = shares.iter().map(|(slots, _)| *slots).collect();
+
+    let mut remaining = budget - slots.iter().sum::<usize>();
+    let mut order: Vec<usize> = (0..weights.len()).collect();
+    order.sort_by(|a, b| shares[*b].1.cmp(&shares[*a].1));
+    for index in order {
+        if remaining == 0 {
+            break;
+        }
+        slots[index] += 1;
+        remaining -= 1;
+    }
+
+    return slots;
+}
+
+/// Turns a seed such as `hip-hop` into a display name such as `Hip Hop`.
+pub fn display_name(seed: &str) -> String {
+    return seed
+        .split('-')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ");
 }
 
 #[derive(ApiResponse)]
 pub enum GenreResponse {
     #[oai(status = 200)]
-    GenreResponse(Json<Vec<String>>),
+    GenreResponse(Json<Vec<UserGenre>>),
+
+    #[oai(status = 404)]
+    NotFound(Json<ResponseError>),
+
+    #[oai(status = 400)]
+    BadRequest(Json<GenresError>),
+}
+
+#[derive(ApiResponse)]
+pub enum GenreCatalogResponse {
+    #[oai(status = 200)]
+    GenreCatalog(Json<Vec<Genre>>),
 
     #[oai(status = 404)]
     NotFound(Json<ResponseError>),
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[oai(status = 401)]
-    BadRequest(Json<ResponseError>),
-}
\ No newline at end of file
+    #[test]
+    fn normalizes_free_text() {
+        assert_eq!(normalize("  Hip Hop "), "hip-hop");
+        assert_eq!(normalize("Drum_and--Bass"), "drum-and-bass");
+        assert_eq!(normalize("metal"), "metal");
+        assert_eq!(normalize("   "), "");
+    }
+
+    #[test]
+    fn spreads_across_parent_and_children() {
+        let children = vec!["deep-house".to_string(), "progressive-house".to_string(), "chicago-house".to_string()];
+        let picked: Vec<String> = (0..5).map(|offset| spread("house", &children, offset)).collect();
+        assert_eq!(picked, vec!["house", "deep-house", "progressive-house", "chicago-house", "house"]);
+    }
+
+    #[test]
+    fn spreads_a_genre_without_children_to_itself() {
+        assert_eq!(spread("jazz", &[], 0), "jazz");
+        assert_eq!(spread("jazz", &[], 7), "jazz");
+    }
+
+    #[test]
+    fn allocates_in_proportion_to_weights() {
+        assert_eq!(allocate(&[3, 1, 1], 5), vec![3, 1, 1]);
+        assert_eq!(allocate(&[1, 1], 4), vec![2, 2]);
+        assert_eq!(allocate(&[2, 1], 4), vec![3, 1]);
+    }
+
+    #[test]
+    fn allocates_nothing_without_weights() {
+        assert_eq!(allocate(&[], 5), Vec::<usize>::new());
+        assert_eq!(allocate(&[0, 0], 5), vec![0, 0]);
+        assert_eq!(allocate(&[2, -1, 0], 3), vec![3, 0, 0]);
+    }
+
+    #[test]
+    fn allocates_a_budget_smaller_than_the_genres() {
+        let slots = allocate(&[1, 1, 1], 2);
+        assert_eq!(slots.iter().sum::<usize>(), 2);
+        assert_eq!(slots, vec![1, 1, 0]);
+        assert_eq!(allocate(&[1, 5, 1], 1), vec![0, 1, 0]);
+        assert_eq!(allocate(&[1, 1], 0), vec![0, 0]);
+    }
+}
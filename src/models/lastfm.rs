@@ -0,0 +1,202 @@
+use serde::{Deserialize, Deserializer};
+use serde::de::Error;
+
+/// Body Last.fm sends instead of the requested resource when a call fails.
+#[derive(Deserialize)]
+pub struct ApiError {
+    pub error: i32,
+    pub message: String,
+}
+
+/// Either the requested resource or the error Last.fm returned for it.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum LastFmResponse<T> {
+    Error(ApiError),
+    Ok(T),
+}
+
+#[derive(Deserialize)]
+pub struct Image {
+    #[serde(rename = "#text")]
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct Tag {
+    pub name: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Tags {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub tag: Vec<Tag>,
+}
+
+#[derive(Deserialize)]
+pub struct Wiki {
+    pub summary: Option<String>,
+    pub content: Option<String>,
+}
+
+/// Counts are sent as strings, e.g. `"listeners": "12345"`.
+#[derive(Deserialize)]
+pub struct Stats {
+    pub listeners: Option<String>,
+    pub playcount: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TrackArtist {
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct TrackInfoResponse {
+    pub track: TrackInfo,
+}
+
+#[derive(Deserialize)]
+pub struct TrackInfo {
+    pub wiki: Option<Wiki>,
+}
+
+#[derive(Deserialize)]
+pub struct ArtistInfoResponse {
+    pub artist: ArtistInfo,
+}
+
+#[derive(Deserialize)]
+pub struct ArtistInfo {
+    pub name: String,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub image: Vec<Image>,
+    pub stats: Option<Stats>,
+    #[serde(default, deserialize_with = "tags_or_empty")]
+    pub tags: Tags,
+    pub bio: Option<Wiki>,
+}
+
+#[derive(Deserialize)]
+pub struct AlbumInfoResponse {
+    pub album: AlbumInfo,
+}
+
+#[derive(Deserialize)]
+pub struct AlbumInfo {
+    pub name: String,
+    pub artist: String,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub image: Vec<Image>,
+    pub listeners: Option<String>,
+    pub playcount: Option<String>,
+    #[serde(default, deserialize_with = "tags_or_empty")]
+    pub tags: Tags,
+    pub wiki: Option<Wiki>,
+}
+
+#[derive(Deserialize)]
+pub struct TagInfoResponse {
+    pub tag: TagInfo,
+}
+
+#[derive(Deserialize)]
+pub struct TagInfo {
+    pub wiki: Option<Wiki>,
+}
+
+#[derive(Deserialize)]
+pub struct RecentTracksResponse {
+    pub recenttracks: RecentTracks,
+}
+
+#[derive(Deserialize)]
+pub struct RecentTracks {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub track: Vec<RecentTrack>,
+    #[serde(rename = "@attr")]
+    pub attr: PageAttr,
+}
+
+#[derive(Deserialize)]
+pub struct PageAttr {
+    #[serde(rename = "totalPages")]
+    pub total_pages: String,
+}
+
+/// A scrobble, the track currently playing has no `date`.
+#[derive(Deserialize)]
+pub struct RecentTrack {
+    pub name: String,
+    pub artist: Text,
+    pub date: Option<ScrobbleDate>,
+}
+
+#[derive(Deserialize)]
+pub struct Text {
+    #[serde(rename = "#text")]
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct ScrobbleDate {
+    pub uts: String,
+}
+
+#[derive(Deserialize)]
+pub struct SimilarTracksResponse {
+    pub similartracks: ChartTracks,
+}
+
+#[derive(Deserialize)]
+pub struct TagTopTracksResponse {
+    pub tracks: ChartTracks,
+}
+
+#[derive(Deserialize)]
+pub struct ChartTracks {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub track: Vec<ChartTrack>,
+}
+
+/// A track in a Last.fm list such as similar tracks or a tag's top tracks.
+#[derive(Deserialize)]
+pub struct ChartTrack {
+    pub name: String,
+    pub artist: TrackArtist,
+}
+
+/// Last.fm sends a list with a single entry as a bare object, and no entries as an empty string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+    Empty(String),
+}
+
+fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error> where D: Deserializer<'de>, T: Deserialize<'de> {
+    return Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(values) => values,
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Empty(value) if value.is_empty() => vec![],
+        OneOrMany::Empty(value) => return Err(D::Error::custom(format!("expected a list, got \"{}\"", value))),
+    });
+}
+
+/// A resource without tags has `"tags": ""` instead of an object.
+fn tags_or_empty<'de, D>(deserializer: D) -> Result<Tags, D::Error> where D: Deserializer<'de> {
+    return Ok(match OneOrMany::<Tags>::deserialize(deserializer)? {
+        OneOrMany::One(tags) => tags,
+        OneOrMany::Many(_) | OneOrMany::Empty(_) => Tags::default(),
+    });
+}
+
+impl Image {
+    /// Picks the largest image with a url, Last.fm lists them from small to mega.
+    pub fn largest(images: &[Image]) -> Option<&str> {
+        return images.iter().rev().map(|image| image.url.as_str()).find(|url| !url.is_empty());
+    }
+}
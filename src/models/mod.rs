@@ -3,4 +3,11 @@ pub mod genres;
 pub mod errors;
 pub mod spotify;
 pub mod user;
+pub mod track;
+pub mod preferences;
+pub mod feedback;
+pub mod playlist;
+pub mod export;
+pub mod lastfm;
+pub mod enrichment;
 
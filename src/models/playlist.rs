@@ -0,0 +1,44 @@
+use chrono::{Duration, NaiveDate};
+
+/// Playlists that always hold the songs of the last few days.
+#[derive(Copy, Clone, PartialEq)]
+pub enum RollingPeriod {
+    Week,
+    Month,
+}
+
+impl RollingPeriod {
+    pub const ALL: [RollingPeriod; 2] = [RollingPeriod::Week, RollingPeriod::Month];
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            RollingPeriod::Week => "MusicApp This Week",
+            RollingPeriod::Month => "MusicApp This Month",
+        };
+    }
+
+    pub fn description(&self) -> &'static str {
+        return match self {
+            RollingPeriod::Week => "MusicApp songs from the last 7 days",
+            RollingPeriod::Month => "MusicApp songs from the last 30 days",
+        };
+    }
+
+    /// First day of the window ending on `today`, inclusive.
+    pub fn start(&self, today: &NaiveDate) -> NaiveDate {
+        let days = match self {
+            RollingPeriod::Week => 7,
+            RollingPeriod::Month => 30,
+        };
+        return *today - Duration::days(days - 1);
+    }
+}
+
+impl From<&RollingPeriod> for String {
+    fn from(value: &RollingPeriod) -> Self {
+        return match value {
+            RollingPeriod::Week => "week".to_string(),
+            RollingPeriod::Month => "month".to_string(),
+        };
+    }
+}
\ No newline at end of file
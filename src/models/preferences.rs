@@ -0,0 +1,141 @@
+use poem_openapi::{ApiResponse, Enum, Object};
+use poem_openapi::payload::Json;
+
+use crate::models::errors::ResponseError;
+
+/// Upper bound for `songs_per_day`, keeps a single generation run within the API quota.
+pub const MAX_SONGS_PER_DAY: i32 = 20;
+
+#[derive(Enum, sqlx::Type, Copy, Clone, PartialEq, Default)]
+#[oai(rename_all = "lowercase")]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+pub enum RecommendationSource {
+    /// Spotify recommendations for the genre, tuned by the other preferences.
+    #[default]
+    Spotify,
+    /// Last.fm tracks similar to liked songs and the genre's top tracks, found on Spotify by search.
+    LastFm,
+}
+
+/// Recommendation tuning for a user, every bound is optional.
+#[derive(Object, Clone)]
+pub struct Preferences {
+    pub min_energy: Option<f32>,
+    pub max_energy: Option<f32>,
+    pub target_energy: Option<f32>,
+    pub min_popularity: Option<i32>,
+    pub max_popularity: Option<i32>,
+    pub target_popularity: Option<i32>,
+    pub min_valence: Option<f32>,
+    pub max_valence: Option<f32>,
+    pub target_valence: Option<f32>,
+    pub min_danceability: Option<f32>,
+    pub max_danceability: Option<f32>,
+    pub target_danceability: Option<f32>,
+    pub min_tempo: Option<f32>,
+    pub max_tempo: Option<f32>,
+    pub target_tempo: Option<f32>,
+    pub min_acousticness: Option<f32>,
+    pub max_acousticness: Option<f32>,
+    pub target_acousticness: Option<f32>,
+    /// How many songs to generate per day, one per saved genre when unset.
+    pub songs_per_day: Option<i32>,
+    /// Where daily songs come from first, the other source is tried when it has nothing new.
+    pub recommendation_source: Option<RecommendationSource>,
+}
+
+impl Default for Preferences {
+    /// The values every user got before preferences existed.
+    fn default() -> Self {
+        Self {
+            min_energy: Some(0.4),
+            max_energy: None,
+            target_energy: None,
+            min_popularity: Some(50),
+            max_popularity: None,
+            target_popularity: None,
+            min_valence: None,
+            max_valence: None,
+            target_valence: None,
+            min_danceability: None,
+            max_danceability: None,
+            target_danceability: None,
+            min_tempo: None,
+            max_tempo: None,
+            target_tempo: None,
+            min_acousticness: None,
+            max_acousticness: None,
+            target_acousticness: None,
+            songs_per_day: None,
+            recommendation_source: None,
+        }
+    }
+}
+
+impl Preferences {
+    /// Returns a message describing the first value outside of the range Spotify accepts.
+    pub fn validate(&self) -> Result<(), String> {
+        let unit_values = [
+            ("energy", [self.min_energy, self.max_energy, self.target_energy]),
+            ("valence", [self.min_valence, self.max_valence, self.target_valence]),
+            ("danceability", [self.min_danceability, self.max_danceability, self.target_danceability]),
+            ("acousticness", [self.min_acousticness, self.max_acousticness, self.target_acousticness]),
+        ];
+        for (name, values) in unit_values {
+            if values.iter().flatten().any(|value| !(0.0..=1.0).contains(value)) {
+                return Err(format!("{} must be between 0 and 1", name));
+            }
+        }
+
+        let popularity = [self.min_popularity, self.max_popularity, self.target_popularity];
+        if popularity.iter().flatten().any(|value| !(0..=100).contains(value)) {
+            return Err("popularity must be between 0 and 100".to_string());
+        }
+
+        let tempo = [self.min_tempo, self.max_tempo, self.target_tempo];
+        if tempo.iter().flatten().any(|value| *value < 0.0) {
+            return Err("tempo must not be negative".to_string());
+        }
+
+        let ranges = [
+            ("energy", self.min_energy, self.max_energy, self.target_energy),
+            ("popularity", self.min_popularity.map(|value| value as f32), self.max_popularity.map(|value| value as f32), self.target_popularity.map(|value| value as f32)),
+            ("valence", self.min_valence, self.max_valence, self.target_valence),
+            ("danceability", self.min_danceability, self.max_danceability, self.target_danceability),
+            ("tempo", self.min_tempo, self.max_tempo, self.target_tempo),
+            ("acousticness", self.min_acousticness, self.max_acousticness, self.target_acousticness),
+        ];
+        for (name, min, max, target) in ranges {
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    return Err(format!("min_{} must not be greater than max_{}", name, name));
+                }
+            }
+            if let Some(target) = target {
+                if min.is_some_and(|min| target < min) || max.is_some_and(|max| target > max) {
+                    return Err(format!("target_{} must be between min_{} and max_{}", name, name, name));
+                }
+            }
+        }
+
+        if let Some(songs_per_day) = self.songs_per_day {
+            if !(1..=MAX_SONGS_PER_DAY).contains(&songs_per_day) {
+                return Err(format!("songs_per_day must be between 1 and {}", MAX_SONGS_PER_DAY));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+#[derive(ApiResponse)]
+pub enum PreferencesResponse {
+    #[oai(status = 200)]
+    Preferences(Json<Preferences>),
+
+    #[oai(status = 404)]
+    NotFound(Json<ResponseError>),
+
+    #[oai(status = 400)]
+    BadRequest(Json<ResponseError>),
+}
\ No newline at end of file
@@ -1,27 +1,54 @@
 use poem_openapi::ApiResponse;
 use poem_openapi::payload::Json;
 
+use crate::models::enrichment::{AlbumDetails, ArtistDetails};
 use crate::models::errors::ResponseError;
-use crate::models::genres::GenreTypes;
 
-#[derive(poem_openapi::Object)]
+#[derive(poem_openapi::Object, Clone)]
 pub struct Song {
     pub id: i32,
     pub title: String,
     pub artist: String,
-    pub genre: GenreTypes,
+    pub genre: String,
     pub link: String,
     pub description: Option<String>,
     pub overview: Option<String>,
     pub album_cover: String,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    pub tempo: Option<f32>,
+    pub key: Option<i32>,
+    pub energy: Option<f32>,
+    pub valence: Option<f32>,
+    pub danceability: Option<f32>,
+    pub duration_ms: Option<i32>,
+    pub spotify_id: Option<String>,
+    pub spotify_uri: Option<String>,
+    pub album: Option<String>,
+    pub release_date: Option<String>,
+    pub artists: Vec<String>,
+    pub artist_ids: Vec<String>,
+    pub preview_url: Option<String>,
+}
+
+impl Song {
+    pub fn track_id(&self) -> Option<&str> {
+        return self.spotify_id.as_deref();
+    }
 }
 
 
+/// A song with the artist and album context stored when it was generated.
+#[derive(poem_openapi::Object)]
+pub struct SongDetail {
+    pub song: Song,
+    pub artist_info: Option<ArtistDetails>,
+    pub album_info: Option<AlbumDetails>,
+}
+
 #[derive(ApiResponse)]
 pub enum SongResponse {
     #[oai(status = 200)]
-    Song(Json<Song>),
+    Song(Json<SongDetail>),
 
     #[oai(status = 404)]
     NotFound(Json<ResponseError>),
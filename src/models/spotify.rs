@@ -8,6 +8,13 @@ pub struct CodePayload {
     pub code: String,
 }
 
+/// An authorize URL plus the values the callback has to be checked against.
+pub struct AuthorizeRequest {
+    pub url: String,
+    pub state: String,
+    pub verifier: String,
+}
+
 #[derive(ApiResponse)]
 pub enum SpotifyResponse {
     #[oai(status = 200)]
@@ -0,0 +1,102 @@
+use std::collections::HashSet;
+
+use poem_openapi::Enum;
+
+use crate::models::song::Song;
+
+/// Spotify only accepts five seeds in total, so at most two are tracks.
+const MAX_TRACK_SEEDS: usize = 2;
+
+#[derive(Clone)]
+pub struct Track {
+    pub id: String,
+    pub uri: String,
+    pub name: String,
+    /// Name of the first credited artist.
+    pub artist: String,
+    pub artists: Vec<String>,
+    pub artist_ids: Vec<String>,
+    pub link: String,
+    pub album: Option<String>,
+    pub release_date: Option<String>,
+    pub album_cover: Option<String>,
+    pub duration_ms: i32,
+    pub preview_url: Option<String>,
+}
+
+/// Spotify audio analysis values stored with each generated song.
+#[derive(Clone, Copy)]
+pub struct AudioFeatures {
+    pub tempo: f32,
+    pub key: i32,
+    pub energy: f32,
+    pub valence: f32,
+    pub danceability: f32,
+}
+
+/// How daily songs are seeded besides the genre itself.
+#[derive(Enum, Copy, Clone, PartialEq, Default)]
+#[oai(rename_all = "lowercase")]
+pub enum SeedStrategy {
+    #[default]
+    Genre,
+    Personal,
+}
+
+/// Spotify artist and track ids used as recommendation seeds.
+#[derive(Clone, Default)]
+pub struct Seeds {
+    pub artists: Vec<String>,
+    pub tracks: Vec<String>,
+}
+
+impl Seeds {
+    /// Takes up to `count` artists and `count` tracks, rotated by `offset` so each genre gets different seeds.
+    pub fn rotate(&self, offset: usize, count: usize) -> Seeds {
+        let pick = |ids: &Vec<String>| -> Vec<String> {
+            if ids.is_empty() {
+                return vec![];
+            }
+            return ids.iter().cycle().skip(offset * count % ids.len()).take(count.min(ids.len())).cloned().collect();
+        };
+
+        return Seeds {
+            artists: pick(&self.artists),
+            tracks: pick(&self.tracks),
+        };
+    }
+
+    /// Puts `tracks` ahead of the current track seeds, keeping within the track seed budget.
+    pub fn with_tracks(self, tracks: Vec<String>) -> Seeds {
+        let tracks = tracks.into_iter().chain(self.tracks).take(MAX_TRACK_SEEDS).collect();
+        return Seeds { artists: self.artists, tracks };
+    }
+}
+
+/// What a newly generated daily song must not be.
+#[derive(Default)]
+pub struct Exclusions {
+    pub songs: Vec<Song>,
+    pub artists: Vec<String>,
+    /// Lowercased artist and title pairs from the user's listening history.
+    pub history: HashSet<(String, String)>,
+}
+
+impl Exclusions {
+    pub fn excludes(&self, track: &Track) -> bool {
+        let known = self.songs.iter().any(|song| song.track_id() == Some(track.id.as_str()));
+        let disliked = self.artists.iter().any(|artist| track.artists.iter().any(|name| name.eq_ignore_ascii_case(artist)));
+        let title = track.name.trim().to_lowercase();
+        let listened = track.artists.iter().any(|artist| self.history.contains(&(artist.trim().to_lowercase(), title.clone())));
+        return known || disliked || listened;
+    }
+
+    /// Checks an artist and title from another service before it is looked up on the provider.
+    pub fn excludes_listing(&self, artist: &str, title: &str) -> bool {
+        let artist = artist.trim().to_lowercase();
+        let title = title.trim().to_lowercase();
+        let known = self.songs.iter().any(|song| song.artist.to_lowercase() == artist && song.title.to_lowercase() == title);
+        let disliked = self.artists.iter().any(|name| name.to_lowercase() == artist);
+        return known || disliked || self.history.contains(&(artist, title));
+    }
+}
\ No newline at end of file
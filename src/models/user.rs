@@ -1,4 +1,15 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use poem_openapi::{ApiResponse, Object};
+use poem_openapi::payload::Json;
+use rspotify::Token;
+
+use crate::models::errors::ResponseError;
+use crate::models::feedback::Feedback;
+use crate::models::genres::UserGenre;
+use crate::models::preferences::Preferences;
+use crate::models::song::Song;
 
 pub struct User {
     pub id: i32,
@@ -6,16 +17,94 @@ pub struct User {
     pub expires_in: i32,
     pub expires_at: Option<DateTime<Utc>>,
     pub refresh_token: Option<String>,
+    pub spotify_id: Option<String>,
+    pub display_name: Option<String>,
+    pub country: Option<String>,
+    pub product: Option<String>,
+    pub lastfm_username: Option<String>,
+}
+
+/// The profile of the account a user logged in with.
+pub struct Account {
+    pub id: String,
+    pub display_name: Option<String>,
+    pub country: Option<String>,
+    pub product: Option<String>,
 }
 
 impl User {
-    pub fn new(id: i32, access_token: String, expires_in: i32, expires_at: Option<DateTime<Utc>>, refresh_token: String) -> Self {
-        Self {
-            id,
-            access_token,
-            expires_in,
-            expires_at,
-            refresh_token: Some(refresh_token),
-        }
+    /// The stored token in the shape the Spotify client expects.
+    pub fn token(&self) -> Token {
+        return Token {
+            access_token: self.access_token.clone(),
+            expires_in: Duration::seconds(self.expires_in as i64),
+            expires_at: self.expires_at,
+            refresh_token: self.refresh_token.clone(),
+            scopes: HashSet::new(),
+        };
+    }
+}
+
+/// The user row without its tokens.
+#[derive(Object)]
+pub struct UserSummary {
+    pub id: i32,
+    pub spotify_id: Option<String>,
+    pub display_name: Option<String>,
+    pub country: Option<String>,
+    pub product: Option<String>,
+    pub token_expires_at: Option<DateTime<Utc>>,
+    pub lastfm_username: Option<String>,
+}
+
+#[derive(Object)]
+pub struct LastFmPayload {
+    pub username: String,
+}
+
+/// A track the user already listened to according to their Last.fm history,
+/// `artist` and `title` are lowercased so Spotify and Last.fm spellings match.
+#[derive(Object, Clone)]
+pub struct KnownTrack {
+    pub artist: String,
+    pub title: String,
+    pub last_played_at: Option<DateTime<Utc>>,
+}
+
+impl KnownTrack {
+    pub fn new(artist: &str, title: &str, last_played_at: Option<DateTime<Utc>>) -> Self {
+        return Self { artist: artist.trim().to_lowercase(), title: title.trim().to_lowercase(), last_played_at };
     }
+}
+
+#[derive(Object)]
+pub struct PlaylistRecord {
+    /// `day`, `week` or `month`.
+    pub period: String,
+    pub day: Option<NaiveDate>,
+    pub playlist_id: String,
+}
+
+/// Everything stored for a user, for data access requests.
+#[derive(Object)]
+pub struct UserExport {
+    pub user: UserSummary,
+    pub genres: Vec<UserGenre>,
+    pub preferences: Option<Preferences>,
+    pub songs: Vec<Song>,
+    pub feedback: Vec<Feedback>,
+    pub playlists: Vec<PlaylistRecord>,
+    pub known_tracks: Vec<KnownTrack>,
+}
+
+#[derive(ApiResponse)]
+pub enum UserExportResponse {
+    #[oai(status = 200)]
+    UserExport(Json<UserExport>),
+
+    #[oai(status = 404)]
+    NotFound(Json<ResponseError>),
+
+    #[oai(status = 401)]
+    BadRequest(Json<ResponseError>),
 }
\ No newline at end of file
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+use crate::services::db::DB;
+use crate::services::lastfm::{LastFM, LastFmError};
+
+/// Pause between tags, Last.fm asks for no more than five calls per second per key.
+const TAG_DELAY: Duration = Duration::from_millis(250);
+
+/// Fills missing genre descriptions from the Last.fm wiki of the matching tag, meant to be spawned at startup.
+pub async fn describe(lastfm: LastFM, db: DB) {
+    let genres = match db.get_undescribed_genres().await {
+        Ok(genres) => genres,
+        Err(err) => {
+            println!("Failed to load genres without a description: {:?}", err);
+            return;
+        }
+    };
+
+    for genre in genres {
+        let summary = match lastfm.tag_info(&genre.replace('-', " ")).await {
+            Ok(tag) => tag.wiki.and_then(|wiki| wiki.summary),
+            Err(LastFmError::NotFound(_)) => None,
+            Err(err) => {
+                println!("Failed to get the Last.fm tag for {}: {}", genre, err);
+                None
+            }
+        };
+
+        // Summaries end in a "Read more on Last.fm" link that does not belong in the catalog.
+        let description = summary.as_deref().map(|summary| summary.split("<a href").next().unwrap_or_default().trim());
+        if let Some(description) = description.filter(|description| !description.is_empty()) {
+            if let Err(err) = db.set_genre_description(&genre, description).await {
+                println!("Failed to save the description of {}: {:?}", genre, err);
+            }
+        }
+        tokio::time::sleep(TAG_DELAY).await;
+    }
+}
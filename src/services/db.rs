@@ -1,8 +1,13 @@
 use chrono::{DateTime, NaiveDate, Utc};
 
-use crate::models::genres::{Genre, GenreTypes};
+use crate::models::enrichment::{AlbumDetails, ArtistDetails};
+use crate::models::feedback::{Feedback, FeedbackKind};
+use crate::models::genres::{DEFAULT_ALIASES, display_name, Genre, parent_of, UserGenre};
+use crate::models::playlist::RollingPeriod;
+use crate::models::preferences::{Preferences, RecommendationSource};
 use crate::models::song::Song;
-use crate::models::user::User;
+use crate::models::track::{AudioFeatures, Track};
+use crate::models::user::{Account, KnownTrack, PlaylistRecord, User};
 
 #[derive(Clone)]
 pub struct DB {
@@ -15,10 +20,13 @@ impl DB {
         return Ok(Self { pool });
     }
 
-    pub async fn save_song(&self, title: &str, artist: &str, link: &str, description: &str, overview: &str, genre: &GenreTypes, album_cover: &str) -> Result<Song, sqlx::Error> {
-        let genre: String = genre.into();
+    pub async fn save_song(&self, user_id: i32, track: &Track, description: Option<&str>, overview: Option<&str>, genre: &str, features: Option<&AudioFeatures>) -> Result<Song, sqlx::Error> {
+        let album_cover = track.album_cover.clone().unwrap_or_default();
 
-        let song = sqlx::query_as!(Song, "INSERT INTO songs (title, artist, link, description, overview, genre, album_cover) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id, title, artist, link, description, overview, created_at, genre, album_cover", title, artist, link, description, overview, genre, album_cover)
+        let song = sqlx::query_as!(Song, "INSERT INTO songs (user_id, title, artist, link, description, overview, genre, album_cover, tempo, key, energy, valence, danceability, duration_ms, spotify_id, spotify_uri, album, release_date, artists, artist_ids, preview_url) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21) RETURNING id, title, artist, link, description, overview, created_at, genre, album_cover, tempo, key, energy, valence, danceability, duration_ms, spotify_id, spotify_uri, album, release_date, artists, artist_ids, preview_url",
+            user_id, track.name, track.artist, track.link, description, overview, genre, album_cover,
+            features.map(|f| f.tempo), features.map(|f| f.key), features.map(|f| f.energy), features.map(|f| f.valence), features.map(|f| f.danceability), track.duration_ms,
+            track.id, track.uri, track.album, track.release_date, &track.artists[..], &track.artist_ids[..], track.preview_url)
             .fetch_one(&self.pool)
             .await?;
 
@@ -26,38 +34,138 @@ impl DB {
     }
 
     pub async fn get_daily_songs(&self, day: &NaiveDate, user_id: i32) -> Result<Vec<Song>, sqlx::Error> {
-        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover FROM songs s WHERE s.user_id = $1 AND s.created_at::date >= $2 and s.created_at::date < $2 + interval '1 day'", user_id, day)
+        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover, s.tempo, s.key, s.energy, s.valence, s.danceability, s.duration_ms, s.spotify_id, s.spotify_uri, s.album, s.release_date, s.artists, s.artist_ids, s.preview_url FROM songs s WHERE s.user_id = $1 AND s.created_at::date >= $2 and s.created_at::date < $2 + interval '1 day'", user_id, day)
             .fetch_all(&self.pool)
             .await?;
         return Ok(songs);
     }
 
-    pub async fn insert_user(&self, access_token: &str, expires_in: i32, expires_at: Option<DateTime<Utc>>, refresh_token: Option<String>) -> Result<User, sqlx::Error> {
-        let user = sqlx::query_as!(User, "INSERT INTO users (access_token, expires_in, expires_at, refresh_token) VALUES ($1, $2, $3, $4) RETURNING id,access_token, expires_in, expires_at, refresh_token", access_token, expires_in, expires_at, refresh_token)
+    /// Gives a user from before accounts were tracked the Spotify account they log in with, unless another user already has it.
+    pub async fn claim_user(&self, user_id: i32, spotify_id: &str) -> Result<bool, sqlx::Error> {
+        let claimed = sqlx::query!("UPDATE users SET spotify_id = $1 WHERE id = $2 AND spotify_id IS NULL AND NOT EXISTS (SELECT 1 FROM users WHERE spotify_id = $1)", spotify_id, user_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        return Ok(claimed > 0);
+    }
+
+    /// Creates the user for a Spotify account or, on repeated logins, updates its tokens and profile.
+    pub async fn upsert_user(&self, account: &Account, access_token: &str, expires_in: i32, expires_at: Option<DateTime<Utc>>, refresh_token: Option<String>) -> Result<User, sqlx::Error> {
+        let user = sqlx::query_as!(User, "INSERT INTO users (spotify_id, display_name, country, product, access_token, expires_in, expires_at, refresh_token) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (spotify_id) DO UPDATE SET display_name = $2, country = $3, product = $4, access_token = $5, expires_in = $6, expires_at = $7, refresh_token = COALESCE($8, users.refresh_token) RETURNING id, access_token, expires_in, expires_at, refresh_token, spotify_id, display_name, country, product, lastfm_username",
+            account.id, account.display_name, account.country, account.product, access_token, expires_in, expires_at, refresh_token)
             .fetch_one(&self.pool)
             .await?;
         return Ok(user);
     }
 
-    pub async fn insert_user_genres(&self, user_id: i32, genre: Vec<GenreTypes>) -> Result<(), sqlx::Error> {
-        let genres: Vec<String> = genre.iter().map(|g| g.into()).collect::<Vec<String>>();
+    /// Adds genres to the user's set, updating the weight of genres that are already saved.
+    pub async fn insert_user_genres(&self, user_id: i32, genres: Vec<(String, i32)>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        for (genre, weight) in genres {
+            sqlx::query!("INSERT INTO user_genres (user_id, genre_id, weight) VALUES ($1, $2, $3) ON CONFLICT (user_id, genre_id) DO UPDATE SET weight = $3", user_id, genre, weight)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    /// Replaces the user's whole genre set in one transaction.
+    pub async fn replace_user_genres(&self, user_id: i32, genres: Vec<(String, i32)>) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
 
-        for genre in genres {
-            sqlx::query!("INSERT INTO user_genres (user_id, genre_id) VALUES ($1, $2)", user_id, genre)
-                .execute(&self.pool)
+        sqlx::query!("DELETE FROM user_genres WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        for (genre, weight) in genres {
+            sqlx::query!("INSERT INTO user_genres (user_id, genre_id, weight) VALUES ($1, $2, $3) ON CONFLICT (user_id, genre_id) DO UPDATE SET weight = $3", user_id, genre, weight)
+                .execute(&mut *tx)
                 .await?;
         }
 
+        tx.commit().await?;
         return Ok(());
     }
 
-    pub async fn get_user_genres(&self, user_id: i32) -> Result<Vec<Genre>, sqlx::Error> {
-        let genres = sqlx::query_as!(Genre, "SELECT name FROM user_genres ug LEFT JOIN genres g ON ug.genre_id = g.name WHERE user_id = $1", user_id)
+    pub async fn delete_user_genre(&self, user_id: i32, genre: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM user_genres WHERE user_id = $1 AND genre_id = $2", user_id, genre)
+            .execute(&self.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+
+    pub async fn get_user_genres(&self, user_id: i32) -> Result<Vec<UserGenre>, sqlx::Error> {
+        let genres = sqlx::query_as!(UserGenre, "SELECT genre_id AS \"name!\", weight FROM user_genres WHERE user_id = $1 ORDER BY weight DESC, genre_id", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(genres);
+    }
+
+    pub async fn get_genre_catalog(&self) -> Result<Vec<Genre>, sqlx::Error> {
+        let genres = sqlx::query_as!(Genre, "SELECT name, display_name, description, parent FROM genres ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(genres);
+    }
+
+    /// Names of catalog genres that still have no description.
+    pub async fn get_undescribed_genres(&self) -> Result<Vec<String>, sqlx::Error> {
+        let genres = sqlx::query_scalar!("SELECT name FROM genres WHERE description IS NULL ORDER BY name")
             .fetch_all(&self.pool)
             .await?;
         return Ok(genres);
     }
 
+    pub async fn set_genre_description(&self, name: &str, description: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE genres SET description = $2 WHERE name = $1", name, description)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    /// Adds seeds missing from the catalog along with guessed parents and the default aliases,
+    /// leaving existing display names, descriptions and parents alone.
+    pub async fn insert_genre_seeds(&self, seeds: &[String]) -> Result<(), sqlx::Error> {
+        let display_names: Vec<String> = seeds.iter().map(|seed| display_name(seed)).collect();
+        let (children, parents): (Vec<String>, Vec<String>) = seeds.iter()
+            .flat_map(|seed| parent_of(seed, seeds).map(|parent| (seed.clone(), parent)))
+            .unzip();
+        let (aliases, targets): (Vec<String>, Vec<String>) = DEFAULT_ALIASES.iter()
+            .map(|(alias, genre)| (alias.to_string(), genre.to_string()))
+            .unzip();
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!("INSERT INTO genres (name, display_name) SELECT * FROM UNNEST($1::text[], $2::text[]) ON CONFLICT (name) DO NOTHING", seeds, &display_names[..])
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("UPDATE genres g SET parent = p.parent FROM UNNEST($1::text[], $2::text[]) AS p(name, parent) WHERE g.name = p.name AND g.parent IS NULL", &children[..], &parents[..])
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("INSERT INTO genre_aliases (alias, genre) SELECT a.alias, a.genre FROM UNNEST($1::text[], $2::text[]) AS a(alias, genre) JOIN genres g ON g.name = a.genre ON CONFLICT (alias) DO NOTHING", &aliases[..], &targets[..])
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        return Ok(());
+    }
+
+    /// Resolves each normalized name to a catalog genre, directly or through an alias, in input order.
+    pub async fn resolve_genres(&self, names: &[String]) -> Result<Vec<(String, Option<String>)>, sqlx::Error> {
+        let rows = sqlx::query!(r#"SELECT i.input AS "input!", COALESCE(g.name, a.genre) AS genre FROM UNNEST($1::text[]) WITH ORDINALITY AS i(input, position) LEFT JOIN genres g ON g.name = i.input LEFT JOIN genre_aliases a ON a.alias = i.input ORDER BY i.position"#, names)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(rows.into_iter().map(|row| (row.input, row.genre)).collect());
+    }
+
+    pub async fn get_child_genres(&self, name: &str) -> Result<Vec<String>, sqlx::Error> {
+        let children = sqlx::query_scalar!("SELECT name FROM genres WHERE parent = $1 ORDER BY name", name)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(children);
+    }
+
     pub async fn update_user_token(&self, user_id: i32, access_token: &str, expires_in: i32, expires_at: &DateTime<Utc>, refresh_token: &String) -> Result<(), sqlx::Error> {
         sqlx::query!("UPDATE users SET access_token = $1, expires_in = $2, expires_at = $3, refresh_token = $4 WHERE id = $5", access_token, expires_in, expires_at, refresh_token, user_id)
             .execute(&self.pool)
@@ -65,10 +173,293 @@ impl DB {
         return Ok(());
     }
 
+    pub async fn clear_refresh_token(&self, user_id: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE users SET refresh_token = NULL WHERE id = $1", user_id)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    pub async fn get_song(&self, user_id: i32, song_id: i32) -> Result<Option<Song>, sqlx::Error> {
+        let song = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover, s.tempo, s.key, s.energy, s.valence, s.danceability, s.duration_ms, s.spotify_id, s.spotify_uri, s.album, s.release_date, s.artists, s.artist_ids, s.preview_url FROM songs s WHERE s.user_id = $1 AND s.id = $2", user_id, song_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(song);
+    }
+
+    pub async fn upsert_artist_info(&self, artist: &ArtistDetails) -> Result<i32, sqlx::Error> {
+        let id = sqlx::query_scalar!("INSERT INTO artist_infos (name, url, image, bio_summary, bio_content, listeners, playcount, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (name) DO UPDATE SET url = $2, image = $3, bio_summary = $4, bio_content = $5, listeners = $6, playcount = $7, tags = $8, updated_at = now() RETURNING id",
+            artist.name, artist.url, artist.image, artist.bio_summary, artist.bio_content, artist.listeners, artist.playcount, &artist.tags)
+            .fetch_one(&self.pool)
+            .await?;
+        return Ok(id);
+    }
+
+    pub async fn upsert_album_info(&self, album: &AlbumDetails) -> Result<i32, sqlx::Error> {
+        let id = sqlx::query_scalar!("INSERT INTO album_infos (artist, name, url, image, wiki_summary, wiki_content, listeners, playcount, tags) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) ON CONFLICT (artist, name) DO UPDATE SET url = $3, image = $4, wiki_summary = $5, wiki_content = $6, listeners = $7, playcount = $8, tags = $9, updated_at = now() RETURNING id",
+            album.artist, album.name, album.url, album.image, album.wiki_summary, album.wiki_content, album.listeners, album.playcount, &album.tags)
+            .fetch_one(&self.pool)
+            .await?;
+        return Ok(id);
+    }
+
+    pub async fn link_song_infos(&self, song_id: i32, artist_info_id: Option<i32>, album_info_id: Option<i32>) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE songs SET artist_info_id = $2, album_info_id = $3 WHERE id = $1", song_id, artist_info_id, album_info_id)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    pub async fn get_song_artist_info(&self, song_id: i32) -> Result<Option<ArtistDetails>, sqlx::Error> {
+        let artist = sqlx::query_as!(ArtistDetails, "SELECT a.name, a.url, a.image, a.bio_summary, a.bio_content, a.listeners, a.playcount, a.tags FROM artist_infos a JOIN songs s ON s.artist_info_id = a.id WHERE s.id = $1", song_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(artist);
+    }
+
+    pub async fn get_song_album_info(&self, song_id: i32) -> Result<Option<AlbumDetails>, sqlx::Error> {
+        let album = sqlx::query_as!(AlbumDetails, "SELECT a.artist, a.name, a.url, a.image, a.wiki_summary, a.wiki_content, a.listeners, a.playcount, a.tags FROM album_infos a JOIN songs s ON s.album_info_id = a.id WHERE s.id = $1", song_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(album);
+    }
+
     pub async fn get_all_songs_from_user(&self, user_id: i32) -> Result<Vec<Song>, sqlx::Error> {
-        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover FROM songs s WHERE s.user_id = $1", user_id)
+        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover, s.tempo, s.key, s.energy, s.valence, s.danceability, s.duration_ms, s.spotify_id, s.spotify_uri, s.album, s.release_date, s.artists, s.artist_ids, s.preview_url FROM songs s WHERE s.user_id = $1", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(songs);
+    }
+
+    pub async fn get_user_preferences(&self, user_id: i32) -> Result<Option<Preferences>, sqlx::Error> {
+        let preferences = sqlx::query_as!(Preferences, "SELECT min_energy, max_energy, target_energy, min_popularity, max_popularity, target_popularity, min_valence, max_valence, target_valence, min_danceability, max_danceability, target_danceability, min_tempo, max_tempo, target_tempo, min_acousticness, max_acousticness, target_acousticness, songs_per_day, recommendation_source AS \"recommendation_source: RecommendationSource\" FROM user_preferences WHERE user_id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(preferences);
+    }
+
+    pub async fn upsert_user_preferences(&self, user_id: i32, preferences: &Preferences) -> Result<Preferences, sqlx::Error> {
+        let preferences = sqlx::query_as!(Preferences, "INSERT INTO user_preferences (user_id, min_energy, max_energy, target_energy, min_popularity, max_popularity, target_popularity, min_valence, max_valence, target_valence, min_danceability, max_danceability, target_danceability, min_tempo, max_tempo, target_tempo, min_acousticness, max_acousticness, target_acousticness, songs_per_day, recommendation_source) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21) ON CONFLICT (user_id) DO UPDATE SET min_energy = $2, max_energy = $3, target_energy = $4, min_popularity = $5, max_popularity = $6, target_popularity = $7, min_valence = $8, max_valence = $9, target_valence = $10, min_danceability = $11, max_danceability = $12, target_danceability = $13, min_tempo = $14, max_tempo = $15, target_tempo = $16, min_acousticness = $17, max_acousticness = $18, target_acousticness = $19, songs_per_day = $20, recommendation_source = $21 RETURNING min_energy, max_energy, target_energy, min_popularity, max_popularity, target_popularity, min_valence, max_valence, target_valence, min_danceability, max_danceability, target_danceability, min_tempo, max_tempo, target_tempo, min_acousticness, max_acousticness, target_acousticness, songs_per_day, recommendation_source AS \"recommendation_source: RecommendationSource\"",
+            user_id,
+            preferences.min_energy, preferences.max_energy, preferences.target_energy,
+            preferences.min_popularity, preferences.max_popularity, preferences.target_popularity,
+            preferences.min_valence, preferences.max_valence, preferences.target_valence,
+            preferences.min_danceability, preferences.max_danceability, preferences.target_danceability,
+            preferences.min_tempo, preferences.max_tempo, preferences.target_tempo,
+            preferences.min_acousticness, preferences.max_acousticness, preferences.target_acousticness,
+            preferences.songs_per_day, preferences.recommendation_source as Option<RecommendationSource>)
+            .fetch_one(&self.pool)
+            .await?;
+        return Ok(preferences);
+    }
+
+    /// Stores feedback for a song the user owns, returning `false` when there is no such song.
+    pub async fn save_feedback(&self, user_id: i32, song_id: i32, kind: &FeedbackKind) -> Result<bool, sqlx::Error> {
+        let kind: String = kind.into();
+        let result = sqlx::query!("INSERT INTO song_feedback (user_id, song_id, kind) SELECT s.user_id, s.id, $3 FROM songs s WHERE s.id = $2 AND s.user_id = $1 ON CONFLICT (user_id, song_id) DO UPDATE SET kind = $3, created_at = now()", user_id, song_id, kind)
+            .execute(&self.pool)
+            .await?;
+        return Ok(result.rows_affected() > 0);
+    }
+
+    pub async fn get_liked_songs(&self, user_id: i32) -> Result<Vec<Song>, sqlx::Error> {
+        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover, s.tempo, s.key, s.energy, s.valence, s.danceability, s.duration_ms, s.spotify_id, s.spotify_uri, s.album, s.release_date, s.artists, s.artist_ids, s.preview_url FROM songs s JOIN song_feedback f ON f.song_id = s.id WHERE f.user_id = $1 AND f.kind = 'like' ORDER BY f.created_at DESC", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(songs);
+    }
+
+    pub async fn get_disliked_artists(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+        let artists = sqlx::query_scalar!("SELECT DISTINCT s.artist FROM songs s JOIN song_feedback f ON f.song_id = s.id WHERE f.user_id = $1 AND f.kind = 'dislike'", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(artists);
+    }
+
+    pub async fn get_daily_playlist(&self, user_id: i32, day: &NaiveDate) -> Result<Option<String>, sqlx::Error> {
+        let playlist_id = sqlx::query_scalar!("SELECT playlist_id FROM daily_playlists WHERE user_id = $1 AND day = $2", user_id, day)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(playlist_id);
+    }
+
+    pub async fn save_daily_playlist(&self, user_id: i32, day: &NaiveDate, playlist_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query!("INSERT INTO daily_playlists (user_id, day, playlist_id) VALUES ($1, $2, $3) ON CONFLICT (user_id, day) DO UPDATE SET playlist_id = $3", user_id, day, playlist_id)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    /// Songs created between `from` and `to`, both days included.
+    pub async fn get_songs_between(&self, user_id: i32, from: &NaiveDate, to: &NaiveDate) -> Result<Vec<Song>, sqlx::Error> {
+        let songs = sqlx::query_as!(Song, "SELECT s.id, s.title, s.artist, s.link, s.description, s.overview, s.created_at, s.genre, s.album_cover, s.tempo, s.key, s.energy, s.valence, s.danceability, s.duration_ms, s.spotify_id, s.spotify_uri, s.album, s.release_date, s.artists, s.artist_ids, s.preview_url FROM songs s WHERE s.user_id = $1 AND s.created_at::date >= $2 AND s.created_at::date <= $3 ORDER BY s.created_at", user_id, from, to)
             .fetch_all(&self.pool)
             .await?;
         return Ok(songs);
     }
+
+    pub async fn get_rolling_playlist(&self, user_id: i32, period: &RollingPeriod) -> Result<Option<String>, sqlx::Error> {
+        let period: String = period.into();
+        let playlist_id = sqlx::query_scalar!("SELECT playlist_id FROM rolling_playlists WHERE user_id = $1 AND period = $2", user_id, period)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(playlist_id);
+    }
+
+    pub async fn save_rolling_playlist(&self, user_id: i32, period: &RollingPeriod, playlist_id: &str) -> Result<(), sqlx::Error> {
+        let period: String = period.into();
+        sqlx::query!("INSERT INTO rolling_playlists (user_id, period, playlist_id) VALUES ($1, $2, $3) ON CONFLICT (user_id, period) DO UPDATE SET playlist_id = $3", user_id, period, playlist_id)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    pub async fn get_user(&self, user_id: i32) -> Result<Option<User>, sqlx::Error> {
+        let user = sqlx::query_as!(User, "SELECT id, access_token, expires_in, expires_at, refresh_token, spotify_id, display_name, country, product, lastfm_username FROM users WHERE id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(user);
+    }
+
+    /// Users with a refresh token whose access token expires before `deadline`.
+    pub async fn get_users_expiring_before(&self, deadline: &DateTime<Utc>) -> Result<Vec<User>, sqlx::Error> {
+        let users = sqlx::query_as!(User, "SELECT id, access_token, expires_in, expires_at, refresh_token, spotify_id, display_name, country, product, lastfm_username FROM users WHERE refresh_token IS NOT NULL AND expires_at < $1", deadline)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(users);
+    }
+
+    /// Links a Last.fm account, dropping the history imported for a previously linked one.
+    pub async fn set_lastfm_username(&self, user_id: i32, username: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let changed = sqlx::query!("UPDATE users SET lastfm_username = $2, lastfm_imported_at = NULL WHERE id = $1 AND lastfm_username IS DISTINCT FROM $2", user_id, username)
+            .execute(&mut *tx)
+            .await?;
+        if changed.rows_affected() > 0 {
+            sqlx::query!("DELETE FROM known_tracks WHERE user_id = $1", user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    pub async fn get_lastfm_username(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+        let username = sqlx::query_scalar!("SELECT lastfm_username FROM users WHERE id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(username.flatten());
+    }
+
+    pub async fn get_lastfm_imported_at(&self, user_id: i32) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let imported_at = sqlx::query_scalar!("SELECT lastfm_imported_at FROM users WHERE id = $1", user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(imported_at.flatten());
+    }
+
+    /// Only records the import while `username` is still the linked account, returns whether it did.
+    pub async fn set_lastfm_imported_at(&self, user_id: i32, username: &str, imported_at: &DateTime<Utc>) -> Result<bool, sqlx::Error> {
+        let updated = sqlx::query!("UPDATE users SET lastfm_imported_at = $3 WHERE id = $1 AND lastfm_username = $2", user_id, username, imported_at)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        return Ok(updated > 0);
+    }
+
+    /// Stores scrobbled tracks by their normalized artist and title, keeping the latest play.
+    pub async fn insert_known_tracks(&self, user_id: i32, tracks: &[KnownTrack]) -> Result<(), sqlx::Error> {
+        let artists: Vec<String> = tracks.iter().map(|track| track.artist.clone()).collect();
+        let titles: Vec<String> = tracks.iter().map(|track| track.title.clone()).collect();
+        let played_at: Vec<Option<DateTime<Utc>>> = tracks.iter().map(|track| track.last_played_at).collect();
+
+        sqlx::query!(r#"INSERT INTO known_tracks (user_id, artist, title, last_played_at) SELECT $1, t.artist, t.title, MAX(t.played_at) FROM UNNEST($2::text[], $3::text[], $4::timestamptz[]) AS t(artist, title, played_at) GROUP BY t.artist, t.title ON CONFLICT (user_id, artist, title) DO UPDATE SET last_played_at = GREATEST(known_tracks.last_played_at, EXCLUDED.last_played_at)"#,
+            user_id, &artists, &titles, &played_at as &[Option<DateTime<Utc>>])
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    pub async fn get_known_tracks(&self, user_id: i32) -> Result<Vec<KnownTrack>, sqlx::Error> {
+        let tracks = sqlx::query_as!(KnownTrack, "SELECT artist, title, last_played_at FROM known_tracks WHERE user_id = $1", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(tracks);
+    }
+
+    /// A cached Last.fm response fetched after `fresh_after`, or after `miss_fresh_after` for a cached miss.
+    /// The outer `None` means nothing usable is cached, the inner one that Last.fm did not know the entry.
+    pub async fn get_lastfm_cache(&self, kind: &str, artist: &str, name: &str, fresh_after: &DateTime<Utc>, miss_fresh_after: &DateTime<Utc>) -> Result<Option<Option<String>>, sqlx::Error> {
+        let row = sqlx::query!("SELECT body FROM lastfm_cache WHERE kind = $1 AND artist = $2 AND name = $3 AND fetched_at > CASE WHEN body IS NULL THEN $5::timestamptz ELSE $4::timestamptz END", kind, artist, name, fresh_after, miss_fresh_after)
+            .fetch_optional(&self.pool)
+            .await?;
+        return Ok(row.map(|row| row.body));
+    }
+
+    pub async fn save_lastfm_cache(&self, kind: &str, artist: &str, name: &str, body: Option<&str>) -> Result<(), sqlx::Error> {
+        sqlx::query!("INSERT INTO lastfm_cache (kind, artist, name, body) VALUES ($1, $2, $3, $4) ON CONFLICT (kind, artist, name) DO UPDATE SET body = $4, fetched_at = now()", kind, artist, name, body)
+            .execute(&self.pool)
+            .await?;
+        return Ok(());
+    }
+
+    /// Deletes cached responses fetched before `fresh_after`, and cached misses fetched before `miss_fresh_after`.
+    pub async fn purge_lastfm_cache(&self, fresh_after: &DateTime<Utc>, miss_fresh_after: &DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!("DELETE FROM lastfm_cache WHERE (body IS NOT NULL AND fetched_at <= $1) OR (body IS NULL AND fetched_at <= $2)", fresh_after, miss_fresh_after)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        return Ok(deleted);
+    }
+
+    /// Removes the user and everything stored for them in a single transaction.
+    pub async fn delete_user(&self, user_id: i32) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!("DELETE FROM song_feedback WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM daily_playlists WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM rolling_playlists WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM user_preferences WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM user_genres WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM known_tracks WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM songs WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM users WHERE id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        return Ok(());
+    }
+
+    pub async fn get_user_feedback(&self, user_id: i32) -> Result<Vec<Feedback>, sqlx::Error> {
+        let rows = sqlx::query!("SELECT song_id, kind FROM song_feedback WHERE user_id = $1 ORDER BY created_at", user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        let feedback = rows.into_iter().map(|row| Feedback { song_id: row.song_id, kind: row.kind.into() }).collect();
+        return Ok(feedback);
+    }
+
+    /// Daily and rolling playlist ids stored for the user.
+    pub async fn get_user_playlists(&self, user_id: i32) -> Result<Vec<PlaylistRecord>, sqlx::Error> {
+        let playlists = sqlx::query_as!(PlaylistRecord, r#"SELECT 'day' AS "period!", day AS "day?", playlist_id AS "playlist_id!" FROM daily_playlists WHERE user_id = $1 UNION ALL SELECT period AS "period!", NULL AS "day?", playlist_id AS "playlist_id!" FROM rolling_playlists WHERE user_id = $1"#, user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        return Ok(playlists);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,44 @@
+use crate::models::song::Song;
+use crate::models::track::{Exclusions, Track};
+use crate::services::lastfm::LastFM;
+use crate::services::provider::{MusicProvider, Provider, ProviderError};
+
+/// How many similar tracks are requested for a liked song.
+const SIMILAR_TRACKS_LIMIT: u32 = 30;
+/// How many of a tag's top tracks are requested per page.
+const TAG_TRACKS_LIMIT: u32 = 50;
+/// Tag chart pages rotated through, so consecutive days do not start from the same tracks.
+const TAG_PAGES: usize = 4;
+/// How many Last.fm candidates are looked up on the provider before giving up on a genre.
+const MAX_SEARCHES: usize = 5;
+
+/// Picks a daily song from Last.fm, tracks similar to `seed` first and then the genre tag's top tracks,
+/// found on the provider by search. Audio preferences do not apply to this source.
+pub async fn daily_song(lastfm: &LastFM, provider: &Provider, genre: &str, seed: Option<&Song>, exclusions: &Exclusions, offset: usize) -> Result<Option<Track>, ProviderError> {
+    let mut candidates = vec![];
+    if let Some(seed) = seed {
+        match lastfm.similar_tracks(&seed.artist, &seed.title, SIMILAR_TRACKS_LIMIT).await {
+            Ok(tracks) => candidates.extend(tracks),
+            Err(err) => println!("Failed to get similar tracks from Last.fm: {}", err),
+        }
+    }
+
+    let page = (offset % TAG_PAGES) as u32 + 1;
+    match lastfm.tag_top_tracks(&genre.replace('-', " "), page, TAG_TRACKS_LIMIT).await {
+        Ok(tracks) => candidates.extend(tracks),
+        Err(err) => println!("Failed to get top {} tracks from Last.fm: {}", genre, err),
+    }
+
+    let candidates = candidates.into_iter()
+        .filter(|candidate| !exclusions.excludes_listing(&candidate.artist.name, &candidate.name))
+        .take(MAX_SEARCHES);
+    for candidate in candidates {
+        if let Some(track) = provider.search_track(&candidate.artist.name, &candidate.name).await? {
+            if !exclusions.excludes(&track) {
+                return Ok(Some(track));
+            }
+        }
+    }
+
+    return Ok(None);
+}
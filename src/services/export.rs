@@ -0,0 +1,84 @@
+use poem_openapi::types::ToJSON;
+
+use crate::models::export::ExportFormat;
+use crate::models::song::Song;
+
+/// Renders songs in the requested playlist or tabular format.
+pub fn render(format: &ExportFormat, songs: &[Song]) -> String {
+    return match format {
+        ExportFormat::M3u => render_m3u(songs),
+        ExportFormat::Xspf => render_xspf(songs),
+        ExportFormat::Csv => render_csv(songs),
+        ExportFormat::Json => render_json(songs),
+    };
+}
+
+fn render_m3u(songs: &[Song]) -> String {
+    let mut output = String::from("#EXTM3U\n");
+    for song in songs {
+        let seconds = song.duration_ms.map(|ms| ms / 1000).unwrap_or(-1);
+        output.push_str(&format!("#EXTINF:{},{} - {}\n", seconds, song.artists.join(", "), song.title));
+        output.push_str(&format!("#EXTIMG:{}\n", song.album_cover));
+        output.push_str(&format!("{}\n", song.link));
+    }
+    return output;
+}
+
+fn render_xspf(songs: &[Song]) -> String {
+    let mut output = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <title>MusicApp</title>\n  <trackList>\n");
+    for song in songs {
+        output.push_str("    <track>\n");
+        output.push_str(&format!("      <location>{}</location>\n", escape_xml(&song.link)));
+        output.push_str(&format!("      <title>{}</title>\n", escape_xml(&song.title)));
+        output.push_str(&format!("      <creator>{}</creator>\n", escape_xml(&song.artists.join(", "))));
+        if let Some(album) = &song.album {
+            output.push_str(&format!("      <album>{}</album>\n", escape_xml(album)));
+        }
+        output.push_str(&format!("      <image>{}</image>\n", escape_xml(&song.album_cover)));
+        if let Some(duration_ms) = song.duration_ms {
+            output.push_str(&format!("      <duration>{}</duration>\n", duration_ms));
+        }
+        output.push_str(&format!("      <annotation>{} - {}</annotation>\n", escape_xml(&song.genre), song.created_at.date_naive()));
+        output.push_str("    </track>\n");
+    }
+    output.push_str("  </trackList>\n</playlist>\n");
+    return output;
+}
+
+fn render_csv(songs: &[Song]) -> String {
+    let mut output = String::from("title,artist,link,album_cover,genre,date\n");
+    for song in songs {
+        let row = [
+            escape_csv(&song.title),
+            escape_csv(&song.artist),
+            escape_csv(&song.link),
+            escape_csv(&song.album_cover),
+            escape_csv(&song.genre),
+            song.created_at.date_naive().to_string(),
+        ];
+        output.push_str(&row.join(","));
+        output.push('\n');
+    }
+    return output;
+}
+
+fn render_json(songs: &[Song]) -> String {
+    let values: Vec<serde_json::Value> = songs.iter().flat_map(|song| song.to_json()).collect();
+    return serde_json::to_string_pretty(&values).unwrap_or_default();
+}
+
+fn escape_xml(value: &str) -> String {
+    return value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;");
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    return value.to_string();
+}
\ No newline at end of file
@@ -0,0 +1,225 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use chrono::{Duration, Utc};
+use rspotify::Token;
+
+use crate::models::preferences::Preferences;
+use crate::models::track::{AudioFeatures, Seeds, Track};
+use crate::models::user::Account;
+use crate::services::provider::{MusicProvider, ProviderError};
+
+/// In-memory provider used when `MUSIC_PROVIDER=fake`, so the whole flow runs without Spotify credentials.
+#[derive(Clone, Default)]
+pub struct FakeProvider {
+    counter: Arc<AtomicU32>,
+    playlists: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+impl FakeProvider {
+    fn next_id(&self) -> u32 {
+        return self.counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn genre_seeds(&self) -> Vec<String> {
+        let seeds = ["pop", "rock", "metal", "jazz", "hip-hop", "electronic", "classical", "country"];
+        return seeds.iter().map(|seed| seed.to_string()).collect();
+    }
+}
+
+impl MusicProvider for FakeProvider {
+    async fn token(&self) -> Option<Token> {
+        let expires_in = Duration::hours(1);
+        return Some(Token {
+            access_token: format!("fake-access-{}", self.next_id()),
+            expires_in,
+            expires_at: Some(Utc::now() + expires_in),
+            refresh_token: Some("fake-refresh".to_string()),
+            scopes: HashSet::new(),
+        });
+    }
+
+    async fn current_user(&self) -> Result<Account, ProviderError> {
+        return Ok(Account {
+            id: "fake-user".to_string(),
+            display_name: Some("Fake User".to_string()),
+            country: Some("US".to_string()),
+            product: Some("premium".to_string()),
+        });
+    }
+
+    async fn top_seeds(&self, limit: u32) -> Result<Seeds, ProviderError> {
+        return Ok(Seeds {
+            artists: (0..limit).map(|id| format!("fakeartist{}", id)).collect(),
+            tracks: (0..limit).map(|id| format!("faketrack{}", id)).collect(),
+        });
+    }
+
+    async fn get_recommendations(&self, genre: &str, _seeds: &Seeds, _preferences: &Preferences, limit: u32) -> Result<Vec<Track>, ProviderError> {
+        let tracks = (0..limit).map(|_| {
+            let id = self.next_id();
+            let artist = format!("Fake {} Artist {}", genre, id % 5);
+            Track {
+                id: format!("fake{}", id),
+                uri: format!("spotify:track:fake{}", id),
+                name: format!("Fake {} Track {}", genre, id),
+                artist: artist.clone(),
+                artists: vec![artist],
+                artist_ids: vec![format!("fakeartist{}", id % 5)],
+                link: format!("https://open.spotify.com/track/fake{}", id),
+                album: Some(format!("Fake {} Album", genre)),
+                release_date: Some("2023-01-01".to_string()),
+                album_cover: Some("https://example.com/fake-album-cover.png".to_string()),
+                duration_ms: 180_000,
+                preview_url: None,
+            }
+        }).collect();
+
+        return Ok(tracks);
+    }
+
+    async fn audio_features(&self, track_id: &str) -> Result<AudioFeatures, ProviderError> {
+        let seed = track_id.len() as i32;
+        return Ok(AudioFeatures {
+            tempo: 120.0,
+            key: seed % 12,
+            energy: 0.5,
+            valence: 0.5,
+            danceability: 0.5,
+        });
+    }
+
+    async fn search_track(&self, artist: &str, title: &str) -> Result<Option<Track>, ProviderError> {
+        let id = self.next_id();
+        return Ok(Some(Track {
+            id: format!("fake{}", id),
+            uri: format!("spotify:track:fake{}", id),
+            name: title.to_string(),
+            artist: artist.to_string(),
+            artists: vec![artist.to_string()],
+            artist_ids: vec![format!("fakeartist{}", id % 5)],
+            link: format!("https://open.spotify.com/track/fake{}", id),
+            album: None,
+            release_date: None,
+            album_cover: None,
+            duration_ms: 180_000,
+            preview_url: None,
+        }));
+    }
+
+    async fn create_playlist(&self, name: &str, _description: &str) -> Result<String, ProviderError> {
+        let playlist_id = format!("fake-playlist-{}-{}", name.to_lowercase().replace(' ', "-"), self.next_id());
+        if let Ok(mut playlists) = self.playlists.lock() {
+            playlists.insert(playlist_id.clone(), vec![]);
+        }
+        return Ok(playlist_id);
+    }
+
+    async fn add_songs_to_playlist(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        if let Ok(mut playlists) = self.playlists.lock() {
+            playlists.entry(playlist_id.to_string()).or_default().extend(songs);
+        }
+        return Ok(());
+    }
+
+    async fn replace_playlist_items(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        if let Ok(mut playlists) = self.playlists.lock() {
+            playlists.insert(playlist_id.to_string(), songs);
+        }
+        return Ok(());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use crate::models::song::Song;
+    use crate::models::track::Exclusions;
+    use crate::services::provider::{CANDIDATE_POOL_SIZE, MAX_CANDIDATE_ATTEMPTS, ProviderKind};
+
+    use super::*;
+
+    fn song(spotify_id: &str) -> Song {
+        return Song {
+            id: 1,
+            title: "Fake rock Track 0".to_string(),
+            artist: "Fake rock Artist 0".to_string(),
+            genre: "rock".to_string(),
+            link: format!("https://open.spotify.com/track/{}", spotify_id),
+            description: None,
+            overview: None,
+            album_cover: String::new(),
+            created_at: Utc::now(),
+            tempo: None,
+            key: None,
+            energy: None,
+            valence: None,
+            danceability: None,
+            duration_ms: None,
+            spotify_id: Some(spotify_id.to_string()),
+            spotify_uri: None,
+            album: None,
+            release_date: None,
+            artists: vec!["Fake rock Artist 0".to_string()],
+            artist_ids: vec![],
+            preview_url: None,
+        };
+    }
+
+    async fn daily_song(exclusions: &Exclusions) -> Option<Track> {
+        let fake = FakeProvider::default();
+        return fake.generate_daily_song("rock", &Seeds::default(), &Preferences::default(), exclusions).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn picks_the_first_candidate() {
+        let track = daily_song(&Exclusions::default()).await.unwrap();
+        assert_eq!(track.id, "fake0");
+    }
+
+    #[tokio::test]
+    async fn skips_previous_songs() {
+        let exclusions = Exclusions { songs: vec![song("fake0")], ..Exclusions::default() };
+        assert_eq!(daily_song(&exclusions).await.unwrap().id, "fake1");
+    }
+
+    #[tokio::test]
+    async fn skips_disliked_artists() {
+        let exclusions = Exclusions { artists: vec!["fake rock artist 0".to_string(), "Fake rock Artist 1".to_string()], ..Exclusions::default() };
+        assert_eq!(daily_song(&exclusions).await.unwrap().id, "fake2");
+    }
+
+    #[tokio::test]
+    async fn skips_listening_history() {
+        let history = [("fake rock artist 0".to_string(), "fake rock track 0".to_string())].into_iter().collect();
+        let exclusions = Exclusions { history, ..Exclusions::default() };
+        assert_eq!(daily_song(&exclusions).await.unwrap().id, "fake1");
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_every_candidate_is_excluded() {
+        let fake = FakeProvider::default();
+        let artists = (0..5).map(|id| format!("Fake rock Artist {}", id)).collect();
+        let exclusions = Exclusions { artists, ..Exclusions::default() };
+        let track = fake.generate_daily_song("rock", &Seeds::default(), &Preferences::default(), &exclusions).await.unwrap();
+        assert!(track.is_none());
+        assert_eq!(fake.next_id(), MAX_CANDIDATE_ATTEMPTS * CANDIDATE_POOL_SIZE);
+    }
+
+    #[tokio::test]
+    async fn fills_and_replaces_a_playlist() {
+        let fake = FakeProvider::default();
+        let provider = ProviderKind::Fake(fake.clone()).login_with_code("fake".to_string()).await.unwrap();
+        let first = provider.generate_daily_song("jazz", &Seeds::default(), &Preferences::default(), &Exclusions::default()).await.unwrap().unwrap();
+        let second = provider.generate_daily_song("jazz", &Seeds::default(), &Preferences::default(), &Exclusions::default()).await.unwrap().unwrap();
+
+        let playlist_id = provider.create_playlist("Daily Songs", "").await.unwrap();
+        provider.add_songs_to_playlist(&playlist_id, vec![first.uri.clone()]).await.unwrap();
+        provider.add_songs_to_playlist(&playlist_id, vec![second.uri.clone()]).await.unwrap();
+        assert_eq!(fake.playlists.lock().unwrap()[&playlist_id], vec![first.uri.clone(), second.uri.clone()]);
+
+        provider.replace_playlist_items(&playlist_id, vec![second.uri.clone()]).await.unwrap();
+        assert_eq!(fake.playlists.lock().unwrap()[&playlist_id], vec![second.uri]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+
+use crate::models::user::KnownTrack;
+use crate::services::db::DB;
+use crate::services::lastfm::{LastFM, LastFmError};
+
+/// Pause between pages, Last.fm asks for no more than five calls per second per key.
+const PAGE_DELAY: Duration = Duration::from_millis(250);
+/// How long to back off when Last.fm rate limits the import.
+const RATE_LIMIT_DELAY: Duration = Duration::from_secs(30);
+/// How many times a rate limited page is retried before the import stops.
+const MAX_PAGE_RETRIES: u32 = 3;
+
+/// Imports the user's scrobbles since their last import into known tracks, meant to be spawned.
+pub async fn import(lastfm: LastFM, db: DB, user_id: i32, username: String) {
+    match import_pages(&lastfm, &db, user_id, &username).await {
+        Ok(count) => println!("Imported {} Last.fm scrobbles for user {}", count, user_id),
+        Err(err) => println!("Failed to import Last.fm history for user {}: {}", user_id, err),
+    }
+}
+
+async fn import_pages(lastfm: &LastFM, db: &DB, user_id: i32, username: &str) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    let started_at = Utc::now();
+    let from = db.get_lastfm_imported_at(user_id).await?.map(|imported_at| imported_at.timestamp());
+    // Scrobbles after the start are left to the next import, so pages don't shift while paging.
+    let to = Some(started_at.timestamp());
+
+    let mut imported = 0;
+    let mut page = 1;
+    let mut retries = 0;
+    loop {
+        let recent = match lastfm.recent_tracks(username, page, from, to).await {
+            Ok(recent) => recent,
+            Err(LastFmError::RateLimited(_)) if retries < MAX_PAGE_RETRIES => {
+                retries += 1;
+                tokio::time::sleep(RATE_LIMIT_DELAY).await;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        retries = 0;
+
+        let tracks: Vec<KnownTrack> = recent.track.iter()
+            .map(|track| {
+                let played_at = track.date.as_ref()
+                    .and_then(|date| date.uts.parse().ok())
+                    .and_then(|uts| Utc.timestamp_opt(uts, 0).single());
+                KnownTrack::new(&track.artist.text, &track.name, played_at)
+            })
+            .collect();
+        if !still_linked(db, user_id, username).await? {
+            println!("Stopped the Last.fm import for user {}, the account was unlinked or changed", user_id);
+            return Ok(imported);
+        }
+        if !tracks.is_empty() {
+            db.insert_known_tracks(user_id, &tracks).await?;
+            imported += tracks.len();
+        }
+
+        let total_pages: u32 = recent.attr.total_pages.parse().unwrap_or(0);
+        if page >= total_pages {
+            break;
+        }
+        page += 1;
+        tokio::time::sleep(PAGE_DELAY).await;
+    }
+
+    if !db.set_lastfm_imported_at(user_id, username, &started_at).await? {
+        println!("Not recording the Last.fm import for user {}, the account was unlinked or changed", user_id);
+    }
+    return Ok(imported);
+}
+
+/// Whether `username` is still the Last.fm account linked to the user.
+async fn still_linked(db: &DB, user_id: i32, username: &str) -> Result<bool, sqlx::Error> {
+    return Ok(db.get_lastfm_username(user_id).await?.as_deref() == Some(username));
+}
@@ -1,33 +1,284 @@
+use std::{env, fmt};
+
+use chrono::{Duration, Utc};
+use serde::de::DeserializeOwned;
+
+use crate::models::lastfm::{AlbumInfo, AlbumInfoResponse, ArtistInfo, ArtistInfoResponse, ChartTrack, LastFmResponse, RecentTracks, RecentTracksResponse, SimilarTracksResponse, TagInfo, TagInfoResponse, TagTopTracksResponse, TrackInfo, TrackInfoResponse};
+use crate::services::db::DB;
+
+/// Used unless `LAST_FM_URL` points somewhere else, e.g. a local stand-in.
+pub const DEFAULT_BASE_URL: &str = "http://ws.audioscrobbler.com/2.0/";
+
+/// The largest page `user.getRecentTracks` allows.
+const RECENT_TRACKS_PAGE_SIZE: u32 = 200;
+
+/// How often expired cache entries are deleted.
+const CACHE_PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Debug)]
+pub enum LastFmError {
+    /// Error 10 or 26, the api key is invalid or suspended.
+    InvalidKey(String),
+    /// Error 6, the track, artist or album does not exist.
+    NotFound(String),
+    /// Error 29, too many calls in a short period.
+    RateLimited(String),
+    /// Any other error code Last.fm answered with.
+    Api { code: i32, message: String },
+    /// The body was neither the requested resource nor a Last.fm error.
+    Parse(serde_json::Error),
+    Http(reqwest::Error),
+}
+
+impl LastFmError {
+    fn from_code(code: i32, message: String) -> Self {
+        return match code {
+            10 | 26 => LastFmError::InvalidKey(message),
+            6 => LastFmError::NotFound(message),
+            29 => LastFmError::RateLimited(message),
+            _ => LastFmError::Api { code, message },
+        };
+    }
+}
+
+impl fmt::Display for LastFmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            LastFmError::InvalidKey(message) => write!(f, "invalid Last.fm api key: {}", message),
+            LastFmError::NotFound(message) => write!(f, "not found on Last.fm: {}", message),
+            LastFmError::RateLimited(message) => write!(f, "rate limited by Last.fm: {}", message),
+            LastFmError::Api { code, message } => write!(f, "Last.fm error {}: {}", code, message),
+            LastFmError::Parse(err) => write!(f, "unexpected Last.fm response: {}", err),
+            LastFmError::Http(err) => write!(f, "{}", err),
+        };
+    }
+}
+
+impl std::error::Error for LastFmError {}
+
+impl From<reqwest::Error> for LastFmError {
+    fn from(value: reqwest::Error) -> Self {
+        return LastFmError::Http(value);
+    }
+}
+
+impl From<serde_json::Error> for LastFmError {
+    fn from(value: serde_json::Error) -> Self {
+        return LastFmError::Parse(value);
+    }
+}
+
+/// Descriptions of a track from Last.fm, `None` when it has no wiki entry.
 pub struct DetailResponse {
-    pub track_info: String,
-    pub track_summary: String,
-    pub track_description: String,
+    pub track_summary: Option<String>,
+    pub track_description: Option<String>,
+}
+
+/// How long cached track, artist and album info is used before asking Last.fm again.
+#[derive(Clone)]
+pub struct CacheTtl {
+    pub hit: Duration,
+    /// Shorter than `hit` so entries Last.fm adds later show up reasonably soon.
+    pub miss: Duration,
+}
+
+impl CacheTtl {
+    /// Reads `LAST_FM_CACHE_TTL_HOURS` and `LAST_FM_CACHE_MISS_TTL_HOURS`, defaulting to a week and a day.
+    pub fn from_env() -> Self {
+        let hours = |name: &str, default: i64| env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+        return Self {
+            hit: Duration::hours(hours("LAST_FM_CACHE_TTL_HOURS", 7 * 24)),
+            miss: Duration::hours(hours("LAST_FM_CACHE_MISS_TTL_HOURS", 24)),
+        };
+    }
 }
 
 #[derive(Clone)]
 pub struct LastFM {
     key: String,
+    base_url: String,
+    client: reqwest::Client,
+    db: DB,
+    ttl: CacheTtl,
 }
 
 impl LastFM {
-    pub async fn new(key: String) -> Result<LastFM, reqwest::Error> {
-        return Ok(Self { key });
+    pub async fn new(key: String, base_url: String, db: DB, ttl: CacheTtl) -> Result<LastFM, reqwest::Error> {
+        let client = reqwest::Client::builder().build()?;
+        return Ok(Self { key, base_url, client, db, ttl });
+    }
+
+    async fn fetch(&self, method: &str, params: &[(&str, &str)]) -> Result<String, LastFmError> {
+        let mut query = vec![("method", method), ("api_key", self.key.as_str()), ("format", "json")];
+        query.extend_from_slice(params);
+
+        return Ok(self.client.get(&self.base_url).query(&query).send().await?.text().await?);
+    }
+
+    /// Calls an api method with url encoded parameters and maps Last.fm error codes to `LastFmError`.
+    async fn call<T: DeserializeOwned>(&self, method: &str, params: &[(&str, &str)]) -> Result<T, LastFmError> {
+        let body = self.fetch(method, params).await?;
+        return parse(&body);
+    }
+
+    /// Like `call`, but answered from the cache while the entry for `kind`, `artist` and `name` is fresh.
+    /// Misses are cached too, any other error is not. A failing cache only costs the network call.
+    async fn cached_call<T: DeserializeOwned>(&self, kind: &str, artist: &str, name: &str, method: &str, params: &[(&str, &str)]) -> Result<T, LastFmError> {
+        let artist = cache_key(artist);
+        let name = cache_key(name);
+        let now = Utc::now();
+        match self.db.get_lastfm_cache(kind, &artist, &name, &(now - self.ttl.hit), &(now - self.ttl.miss)).await {
+            Ok(Some(Some(body))) => return parse(&body),
+            Ok(Some(None)) => return Err(LastFmError::NotFound(format!("cached miss for {} {}", kind, artist))),
+            Ok(None) => {}
+            Err(err) => println!("Failed to read the Last.fm cache: {:?}", err),
+        }
+
+        let body = self.fetch(method, params).await?;
+        let result = parse(&body);
+        let cached = match &result {
+            Ok(_) => Some(Some(body.as_str())),
+            Err(LastFmError::NotFound(_)) => Some(None),
+            Err(_) => None,
+        };
+        if let Some(body) = cached {
+            if let Err(err) = self.db.save_lastfm_cache(kind, &artist, &name, body).await {
+                println!("Failed to write the Last.fm cache: {:?}", err);
+            }
+        }
+
+        return result;
+    }
+
+    /// Deletes cache entries that are too old to be read again, runs until the server stops.
+    pub async fn purge_cache(self) {
+        let mut interval = tokio::time::interval(CACHE_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let now = Utc::now();
+            match self.db.purge_lastfm_cache(&(now - self.ttl.hit), &(now - self.ttl.miss)).await {
+                Ok(deleted) => println!("Purged {} expired Last.fm cache entries", deleted),
+                Err(err) => println!("Failed to purge the Last.fm cache: {:?}", err),
+            }
+        }
     }
 
-    pub async fn get_details(&self, artist_name: &str, track_name: &str) -> Result<DetailResponse, reqwest::Error> {
-        let url = format!("http://ws.audioscrobbler.com/2.0/?method=track.getInfo&api_key={}&artist={}&track={}&format=json", &self.key, artist_name, track_name);
-        let response: serde_json::Value = reqwest::get(&url).await?.json().await?;
+    pub async fn track_info(&self, artist_name: &str, track_name: &str) -> Result<TrackInfo, LastFmError> {
+        let response: TrackInfoResponse = self.cached_call("track", artist_name, track_name, "track.getInfo", &[("artist", artist_name), ("track", track_name), ("autocorrect", "1")]).await?;
+        return Ok(response.track);
+    }
 
-        let track_info = response["track"]["name"].as_str().unwrap_or("Track not found.").to_owned();
-        let track_summary = response["track"]["wiki"]["summary"].as_str().unwrap_or("Summary not found.").to_owned();
-        let track_description = response["track"]["wiki"]["content"].as_str().unwrap_or("Description not found.").to_owned();
+    pub async fn artist_info(&self, artist_name: &str) -> Result<ArtistInfo, LastFmError> {
+        let response: ArtistInfoResponse = self.cached_call("artist", artist_name, "", "artist.getInfo", &[("artist", artist_name), ("autocorrect", "1")]).await?;
+        return Ok(response.artist);
+    }
+
+    pub async fn album_info(&self, artist_name: &str, album_name: &str) -> Result<AlbumInfo, LastFmError> {
+        let response: AlbumInfoResponse = self.cached_call("album", artist_name, album_name, "album.getInfo", &[("artist", artist_name), ("album", album_name), ("autocorrect", "1")]).await?;
+        return Ok(response.album);
+    }
+
+    pub async fn tag_info(&self, tag: &str) -> Result<TagInfo, LastFmError> {
+        let response: TagInfoResponse = self.cached_call("tag", tag, "", "tag.getInfo", &[("tag", tag)]).await?;
+        return Ok(response.tag);
+    }
+
+    /// One page of a user's scrobbles, newest first, optionally only those between `from` and `to` (unix seconds).
+    pub async fn recent_tracks(&self, username: &str, page: u32, from: Option<i64>, to: Option<i64>) -> Result<RecentTracks, LastFmError> {
+        let page = page.to_string();
+        let limit = RECENT_TRACKS_PAGE_SIZE.to_string();
+        let from = from.map(|from| from.to_string());
+        let to = to.map(|to| to.to_string());
+        let mut params = vec![("user", username), ("page", page.as_str()), ("limit", limit.as_str())];
+        if let Some(from) = &from {
+            params.push(("from", from.as_str()));
+        }
+        if let Some(to) = &to {
+            params.push(("to", to.as_str()));
+        }
+
+        let response: RecentTracksResponse = self.call("user.getRecentTracks", &params).await?;
+        return Ok(response.recenttracks);
+    }
+
+    pub async fn similar_tracks(&self, artist_name: &str, track_name: &str, limit: u32) -> Result<Vec<ChartTrack>, LastFmError> {
+        let limit = limit.to_string();
+        let response: SimilarTracksResponse = self.call("track.getSimilar", &[("artist", artist_name), ("track", track_name), ("limit", limit.as_str()), ("autocorrect", "1")]).await?;
+        return Ok(response.similartracks.track);
+    }
+
+    pub async fn tag_top_tracks(&self, tag: &str, page: u32, limit: u32) -> Result<Vec<ChartTrack>, LastFmError> {
+        let page = page.to_string();
+        let limit = limit.to_string();
+        let response: TagTopTracksResponse = self.call("tag.getTopTracks", &[("tag", tag), ("page", page.as_str()), ("limit", limit.as_str())]).await?;
+        return Ok(response.tracks.track);
+    }
 
-        let detail_response = DetailResponse {
-            track_info,
+    /// Track summary and description for a daily song, a track Last.fm does not know has no details.
+    pub async fn get_details(&self, artist_name: &str, track_name: &str) -> Result<DetailResponse, LastFmError> {
+        let track = match self.track_info(artist_name, track_name).await {
+            Ok(track) => track,
+            Err(LastFmError::NotFound(_)) => return Ok(DetailResponse { track_summary: None, track_description: None }),
+            Err(err) => return Err(err),
+        };
+
+        let (track_summary, track_description) = match track.wiki {
+            Some(wiki) => (wiki.summary, wiki.content),
+            None => (None, None),
+        };
+
+        return Ok(DetailResponse {
             track_summary,
             track_description,
+        });
+    }
+}
+
+fn parse<T: DeserializeOwned>(body: &str) -> Result<T, LastFmError> {
+    return match serde_json::from_str::<LastFmResponse<T>>(body)? {
+        LastFmResponse::Error(err) => Err(LastFmError::from_code(err.error, err.message)),
+        LastFmResponse::Ok(value) => Ok(value),
+    };
+}
+
+/// Normalizes a name for cache lookups, so `The Beatles ` and `the  beatles` share an entry.
+fn cache_key(value: &str) -> String {
+    return value.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(body: &str) -> LastFmError {
+        return match parse::<ArtistInfoResponse>(body) {
+            Ok(_) => panic!("expected an error for {}", body),
+            Err(err) => err,
         };
+    }
 
-        return Ok(detail_response);
+    #[test]
+    fn maps_error_codes() {
+        assert!(matches!(parse_error(r#"{"error": 6, "message": "Track not found"}"#), LastFmError::NotFound(_)));
+        assert!(matches!(parse_error(r#"{"error": 10, "message": "Invalid API key"}"#), LastFmError::InvalidKey(_)));
+        assert!(matches!(parse_error(r#"{"error": 26, "message": "Suspended API key"}"#), LastFmError::InvalidKey(_)));
+        assert!(matches!(parse_error(r#"{"error": 29, "message": "Rate limit exceeded"}"#), LastFmError::RateLimited(_)));
+        assert!(matches!(parse_error(r#"{"error": 11, "message": "Service Offline"}"#), LastFmError::Api { code: 11, .. }));
+        assert!(matches!(parse_error(r#"{"unexpected": true}"#), LastFmError::Parse(_)));
     }
-}
\ No newline at end of file
+
+    fn artist_tags(tags: &str) -> Vec<String> {
+        let body = format!(r#"{{"artist": {{"name": "Artist", "tags": {}}}}}"#, tags);
+        let response: ArtistInfoResponse = parse(&body).unwrap();
+        return response.artist.tags.tag.into_iter().map(|tag| tag.name).collect();
+    }
+
+    #[test]
+    fn parses_tag_shapes() {
+        assert!(artist_tags(r#""""#).is_empty());
+        assert_eq!(artist_tags(r#"{"tag": {"name": "rock", "url": "https://www.last.fm/tag/rock"}}"#), vec!["rock"]);
+        assert_eq!(artist_tags(r#"{"tag": [{"name": "rock"}, {"name": "indie"}]}"#), vec!["rock", "indie"]);
+        assert!(artist_tags(r#"{"tag": ""}"#).is_empty());
+    }
+}
@@ -1,3 +1,9 @@
 pub mod db;
 pub mod spotify;
-pub mod lastfm;
\ No newline at end of file
+pub mod lastfm;
+pub mod provider;
+pub mod fake;
+pub mod export;
+pub mod history;
+pub mod discovery;
+pub mod catalog;
\ No newline at end of file
@@ -0,0 +1,249 @@
+use std::{env, fmt};
+use std::time::Duration;
+
+use poem::http::StatusCode;
+use rspotify::{ClientError, OAuth, Token};
+use rspotify::model::IdError;
+
+use crate::models::preferences::Preferences;
+use crate::models::spotify::AuthorizeRequest;
+use crate::models::track::{AudioFeatures, Exclusions, Seeds, Track};
+use crate::models::user::Account;
+use crate::services::fake::FakeProvider;
+use crate::services::spotify::Spotify;
+
+/// How many recommendations are requested per call when looking for a new daily song.
+pub const CANDIDATE_POOL_SIZE: u32 = 20;
+/// How many candidate pools are requested before giving up on a genre.
+pub const MAX_CANDIDATE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// Still rate limited after every retry, `retry_after` is the last wait the service asked for.
+    QuotaExhausted { retry_after: Option<Duration> },
+    InvalidId(IdError),
+    /// The client had no token to use or refresh.
+    TokenUnavailable,
+    /// The token endpoint rejected a refresh, with the error body it sent.
+    RefreshRejected(String),
+    Client(ClientError),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            ProviderError::QuotaExhausted { retry_after: Some(retry_after) } => write!(f, "rate limited by the music provider, retry after {} seconds", retry_after.as_secs()),
+            ProviderError::QuotaExhausted { retry_after: None } => write!(f, "rate limited by the music provider"),
+            ProviderError::InvalidId(err) => write!(f, "invalid id: {}", err),
+            ProviderError::TokenUnavailable => write!(f, "no token available"),
+            ProviderError::RefreshRejected(body) => write!(f, "token refresh rejected: {}", body),
+            ProviderError::Client(err) => write!(f, "{}", err),
+        };
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl ProviderError {
+    /// Whether the refresh token can never be used again, e.g. because the user revoked access.
+    pub fn is_revoked(&self) -> bool {
+        return matches!(self, ProviderError::RefreshRejected(body) if body.contains("invalid_grant"));
+    }
+}
+
+impl From<ClientError> for ProviderError {
+    fn from(value: ClientError) -> Self {
+        return ProviderError::Client(value);
+    }
+}
+
+impl From<IdError> for ProviderError {
+    fn from(value: IdError) -> Self {
+        return ProviderError::InvalidId(value);
+    }
+}
+
+impl From<ProviderError> for poem::Error {
+    fn from(value: ProviderError) -> Self {
+        let status = match value {
+            ProviderError::QuotaExhausted { .. } => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::NOT_ACCEPTABLE,
+        };
+        return poem::Error::new(value, status);
+    }
+}
+
+/// Operations the daily song and playlist flows need from a music service.
+pub trait MusicProvider {
+    async fn token(&self) -> Option<Token>;
+
+    async fn current_user(&self) -> Result<Account, ProviderError>;
+
+    /// Returns the user's own top artists and tracks, for personal seeding.
+    async fn top_seeds(&self, limit: u32) -> Result<Seeds, ProviderError>;
+
+    async fn get_recommendations(&self, genre: &str, seeds: &Seeds, preferences: &Preferences, limit: u32) -> Result<Vec<Track>, ProviderError>;
+
+    async fn audio_features(&self, track_id: &str) -> Result<AudioFeatures, ProviderError>;
+
+    /// Finds the provider's track for an artist and title from another service, `None` when it has no match.
+    async fn search_track(&self, artist: &str, title: &str) -> Result<Option<Track>, ProviderError>;
+
+    async fn create_playlist(&self, name: &str, description: &str) -> Result<String, ProviderError>;
+
+    async fn add_songs_to_playlist(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError>;
+
+    async fn replace_playlist_items(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError>;
+
+    /// Picks a track for `genre` that `exclusions` allows, fetching a pool of candidates
+    /// per attempt and returning `None` once `MAX_CANDIDATE_ATTEMPTS` pools had nothing new.
+    async fn generate_daily_song(&self, genre: &str, seeds: &Seeds, preferences: &Preferences, exclusions: &Exclusions) -> Result<Option<Track>, ProviderError> {
+        for _ in 0..MAX_CANDIDATE_ATTEMPTS {
+            let candidates = self.get_recommendations(genre, seeds, preferences, CANDIDATE_POOL_SIZE).await?;
+            if candidates.is_empty() {
+                return Ok(None);
+            }
+
+            let track = candidates.into_iter().find(|track| !exclusions.excludes(track));
+            if track.is_some() {
+                return Ok(track);
+            }
+        }
+
+        return Ok(None);
+    }
+}
+
+/// Which provider implementation handlers should build, chosen once at startup.
+#[derive(Clone)]
+pub enum ProviderKind {
+    Spotify,
+    Fake(FakeProvider),
+}
+
+impl ProviderKind {
+    /// Reads `MUSIC_PROVIDER`, defaulting to Spotify unless it is set to `fake`.
+    pub fn from_env() -> Self {
+        return match env::var("MUSIC_PROVIDER") {
+            Ok(kind) if kind.eq_ignore_ascii_case("fake") => ProviderKind::Fake(FakeProvider::default()),
+            _ => ProviderKind::Spotify,
+        };
+    }
+
+    pub async fn login_with_code(&self, code: String) -> Result<Provider, ProviderError> {
+        return match self {
+            ProviderKind::Spotify => Ok(Provider::Spotify(Spotify::from_code(code).await?)),
+            ProviderKind::Fake(fake) => Ok(Provider::Fake(fake.clone())),
+        };
+    }
+
+    pub fn authorize_url(&self) -> Result<AuthorizeRequest, ProviderError> {
+        return match self {
+            ProviderKind::Spotify => Spotify::authorize_url(),
+            ProviderKind::Fake(_) => {
+                let state = OAuth::default().state;
+                let url = format!("http://localhost:3000/api/spotify/callback?code=fake&state={}", state);
+                Ok(AuthorizeRequest { url, state, verifier: "fake-verifier".to_string() })
+            }
+        };
+    }
+
+    pub async fn login_with_pkce(&self, code: &str, verifier: String) -> Result<Provider, ProviderError> {
+        return match self {
+            ProviderKind::Spotify => Ok(Provider::Spotify(Spotify::from_pkce_code(code, verifier).await?)),
+            ProviderKind::Fake(fake) => Ok(Provider::Fake(fake.clone())),
+        };
+    }
+
+    pub fn with_token(&self, token: Token) -> Provider {
+        return match self {
+            ProviderKind::Spotify => Provider::Spotify(Spotify::from_token(token)),
+            ProviderKind::Fake(fake) => Provider::Fake(fake.clone()),
+        };
+    }
+
+    /// Genre seeds the provider can recommend for, used to fill the genre catalog.
+    pub async fn genre_seeds(&self) -> Result<Vec<String>, ProviderError> {
+        return match self {
+            ProviderKind::Spotify => Spotify::genre_seeds().await,
+            ProviderKind::Fake(fake) => Ok(fake.genre_seeds()),
+        };
+    }
+
+    pub async fn refresh_token(&self, token: Token) -> Result<Token, ProviderError> {
+        return match self {
+            ProviderKind::Spotify => Spotify::refresh(token).await,
+            ProviderKind::Fake(fake) => fake.token().await.ok_or(ProviderError::TokenUnavailable),
+        };
+    }
+}
+
+pub enum Provider {
+    Spotify(Spotify),
+    Fake(FakeProvider),
+}
+
+impl MusicProvider for Provider {
+    async fn token(&self) -> Option<Token> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.token().await,
+            Provider::Fake(fake) => fake.token().await,
+        };
+    }
+
+    async fn current_user(&self) -> Result<Account, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.current_user().await,
+            Provider::Fake(fake) => fake.current_user().await,
+        };
+    }
+
+    async fn top_seeds(&self, limit: u32) -> Result<Seeds, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.top_seeds(limit).await,
+            Provider::Fake(fake) => fake.top_seeds(limit).await,
+        };
+    }
+
+    async fn get_recommendations(&self, genre: &str, seeds: &Seeds, preferences: &Preferences, limit: u32) -> Result<Vec<Track>, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.get_recommendations(genre, seeds, preferences, limit).await,
+            Provider::Fake(fake) => fake.get_recommendations(genre, seeds, preferences, limit).await,
+        };
+    }
+
+    async fn audio_features(&self, track_id: &str) -> Result<AudioFeatures, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.audio_features(track_id).await,
+            Provider::Fake(fake) => fake.audio_features(track_id).await,
+        };
+    }
+
+    async fn search_track(&self, artist: &str, title: &str) -> Result<Option<Track>, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.search_track(artist, title).await,
+            Provider::Fake(fake) => fake.search_track(artist, title).await,
+        };
+    }
+
+    async fn create_playlist(&self, name: &str, description: &str) -> Result<String, ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.create_playlist(name, description).await,
+            Provider::Fake(fake) => fake.create_playlist(name, description).await,
+        };
+    }
+
+    async fn add_songs_to_playlist(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.add_songs_to_playlist(playlist_id, songs).await,
+            Provider::Fake(fake) => fake.add_songs_to_playlist(playlist_id, songs).await,
+        };
+    }
+
+    async fn replace_playlist_items(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        return match self {
+            Provider::Spotify(spotify) => spotify.replace_playlist_items(playlist_id, songs).await,
+            Provider::Fake(fake) => fake.replace_playlist_items(playlist_id, songs).await,
+        };
+    }
+}
\ No newline at end of file
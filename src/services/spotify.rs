@@ -1,15 +1,26 @@
-use std::borrow::Cow;
-use std::fmt::format;
-use chrono::NaiveDate;
-use poem::session::Session;
-use rspotify::{AuthCodeSpotify, ClientError, Credentials, OAuth, scopes, Token};
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use poem::http::StatusCode;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+use rspotify::{AuthCodePkceSpotify, AuthCodeSpotify, ClientCredsSpotify, ClientError, Credentials, OAuth, scopes, Token};
+use rspotify::http::{HttpError, Query};
 use rspotify::clients::{BaseClient, OAuthClient};
-use rspotify::model::{IdError, PlayableId, PlaylistId, Recommendations, SimplifiedAlbum, SimplifiedTrack, TrackId, UserId};
-use rspotify::model::SearchResult::Albums;
-use rspotify::model::SearchType::Album;
+use rspotify::model::{ArtistId, FullTrack, Id, PlayableId, PlaylistId, Recommendations, RecommendationsAttribute, SearchResult, SearchType, TimeRange, TrackId};
 
-use crate::models::genres::GenreTypes;
-use crate::token::token::write_token;
+use crate::models::spotify::AuthorizeRequest;
+use crate::models::preferences::Preferences;
+use crate::models::track::{AudioFeatures, Seeds, Track};
+use crate::models::user::Account;
+use crate::services::provider::{MusicProvider, ProviderError};
+
+/// Body of `recommendations/available-genre-seeds`.
+#[derive(Deserialize)]
+struct GenreSeeds {
+    genres: Vec<String>,
+}
 
 #[derive(Clone)]
 pub struct Spotify {
@@ -18,16 +29,9 @@ pub struct Spotify {
 
 
 impl Spotify {
-    pub async fn from_code(code: String) -> Result<Self, ClientError> {
+    pub async fn from_code(code: String) -> Result<Self, ProviderError> {
         let creds = Credentials::from_env().expect("RSPOTIFY_CLIENT_ID and RSPOTIFY_CLIENT_SECRET must be set in the environment");
-
-        // Using every possible scope
-        let scopes = scopes!(
-        "user-read-private",
-        "playlist-modify-public"
-        );
-
-        let oauth = OAuth::from_env(scopes).unwrap();
+        let oauth = OAuth::from_env(Self::scopes()).unwrap();
 
         let client = AuthCodeSpotify::new(creds, oauth);
 
@@ -43,98 +47,316 @@ impl Spotify {
         return Ok(Self { client });
     }
 
-    pub async fn from_token(token: Token, session: &Session) -> Result<Self, ClientError> {
-        let client = AuthCodeSpotify::from_token(token.clone());
-
-        match token.expires_at {
-            Some(expires_at) => {
-                let now = chrono::Utc::now();
-                if now > expires_at {
-                    match client.refresh_token().await {
-                        Ok(_) => {
-                            match write_token(token, &session) {
-                                Ok(_) => {
-                                    println!("Refreshed token successfully");
-                                }
-                                Err(err) => {
-                                    println!("Failed to write token: {:?}", err)
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            return Err(err);
-                        }
-                    }
+    /// Builds the authorize URL for the PKCE flow, along with the state and verifier the callback has to match.
+    pub fn authorize_url() -> Result<AuthorizeRequest, ProviderError> {
+        let creds = Credentials::from_env().expect("RSPOTIFY_CLIENT_ID must be set in the environment");
+        let oauth = OAuth::from_env(Self::scopes()).expect("RSPOTIFY_REDIRECT_URI must be set in the environment");
+
+        let mut client = AuthCodePkceSpotify::new(creds, oauth);
+        let url = client.get_authorize_url(None)?;
+        let verifier = client.verifier.clone().ok_or(ProviderError::TokenUnavailable)?;
+
+        return Ok(AuthorizeRequest { url, state: client.oauth.state.clone(), verifier });
+    }
+
+    /// Completes the PKCE flow started by `authorize_url`.
+    pub async fn from_pkce_code(code: &str, verifier: String) -> Result<Self, ProviderError> {
+        let creds = Credentials::from_env().expect("RSPOTIFY_CLIENT_ID must be set in the environment");
+        let oauth = OAuth::from_env(Self::scopes()).expect("RSPOTIFY_REDIRECT_URI must be set in the environment");
+
+        let mut client = AuthCodePkceSpotify::new(creds, oauth);
+        client.verifier = Some(verifier);
+        client.request_token(code).await?;
+
+        let token = client.token.lock().await.map_err(|_| ProviderError::TokenUnavailable)?.clone();
+        let token = token.ok_or(ProviderError::TokenUnavailable)?;
+        return Ok(Self::from_token(token));
+    }
+
+    /// Fetches the available genre seeds with an app token, no user needs to be logged in.
+    pub async fn genre_seeds() -> Result<Vec<String>, ProviderError> {
+        let creds = Credentials::from_env().expect("RSPOTIFY_CLIENT_ID and RSPOTIFY_CLIENT_SECRET must be set in the environment");
+        let client = ClientCredsSpotify::new(creds);
+        with_retry(|| client.request_token()).await?;
+
+        let query = Query::new();
+        let body = with_retry(|| client.api_get("recommendations/available-genre-seeds", &query)).await?;
+        let seeds: GenreSeeds = serde_json::from_str(&body).map_err(ClientError::from)?;
+        return Ok(seeds.genres);
+    }
+
+    fn scopes() -> HashSet<String> {
+        return scopes!(
+        "user-read-private",
+        "user-top-read",
+        "playlist-modify-public",
+        "playlist-modify-private"
+        );
+    }
+
+    pub fn from_token(token: Token) -> Self {
+        let client = AuthCodeSpotify::from_token(token);
+        return Self { client };
+    }
+
+    /// Exchanges the refresh token for a new access token.
+    pub async fn refresh(token: Token) -> Result<Token, ProviderError> {
+        let creds = Credentials::from_env().expect("RSPOTIFY_CLIENT_ID and RSPOTIFY_CLIENT_SECRET must be set in the environment");
+        let client = AuthCodeSpotify::new(creds, OAuth::default());
+        *client.token.lock().await.map_err(|_| ProviderError::TokenUnavailable)? = Some(token);
+
+        match with_retry(|| client.refresh_token()).await {
+            Ok(_) => {}
+            // Spotify answers 400 with `invalid_grant` once the user revoked access or the refresh token expired.
+            Err(ProviderError::Client(ClientError::Http(http))) => return Err(match *http {
+                HttpError::StatusCode(response) if response.status() == StatusCode::BAD_REQUEST => {
+                    ProviderError::RefreshRejected(response.text().await.unwrap_or_default())
                 }
-            }
-            None => {}
+                http => ProviderError::Client(ClientError::Http(Box::new(http))),
+            }),
+            Err(err) => return Err(err),
         }
 
-        return Ok(Self { client });
+        let refreshed = client.token.lock().await.map_err(|_| ProviderError::TokenUnavailable)?.clone();
+        return refreshed.ok_or(ProviderError::TokenUnavailable);
     }
 
-    pub async fn get_recommendations(&self, genre: String, limit: u32) -> Result<Recommendations, ClientError> {
-        let attributes = [
-            rspotify::model::RecommendationsAttribute::MinEnergy(0.4),
-            rspotify::model::RecommendationsAttribute::MinPopularity(50),
-        ];
+    async fn recommendations(&self, genre: &str, seeds: &Seeds, preferences: &Preferences, limit: u32) -> Result<Recommendations, ProviderError> {
+        let attributes = recommendation_attributes(preferences);
 
-        let genre = genre.as_str();
+        let artists: Vec<ArtistId> = seeds.artists.iter().flat_map(|id| ArtistId::from_id(id.as_str()).ok()).collect();
+        let tracks: Vec<TrackId> = seeds.tracks.iter().flat_map(|id| TrackId::from_id(id.as_str()).ok()).collect();
 
-        let recommendations = self.client.recommendations(
-            attributes,
-            Some([]),
+        let recommendations = with_retry(|| self.client.recommendations(
+            attributes.clone(),
+            Some(artists.clone()),
             Some([genre]),
-            Some([]),
+            Some(tracks.clone()),
             None,
             Some(limit),
-        ).await?;
+        )).await?;
 
         return Ok(recommendations);
     }
+}
 
-    pub async fn search_track_details(&self, artist: &str, track: &str) -> Option<Vec<SimplifiedAlbum>> {
-        let query = format!("{} {}", artist, track);
-        let search_result = match self.client.search(&query, Album, None, None, Some(1), Some(0)).await {
-            Ok(result) => result,
-            Err(_) => return None,
+impl MusicProvider for Spotify {
+    async fn token(&self) -> Option<Token> {
+        return match self.client.token.lock().await {
+            Ok(token) => token.clone(),
+            Err(_) => None,
         };
+    }
 
-        return match search_result {
-            Albums(album) => Some(album.items),
-            _ => None,
-        };
+    async fn current_user(&self) -> Result<Account, ProviderError> {
+        let user = with_retry(|| self.client.current_user()).await?;
+
+        return Ok(Account {
+            id: user.id.id().to_string(),
+            display_name: user.display_name,
+            country: user.country.as_ref().and_then(serialized_name),
+            product: user.product.as_ref().and_then(serialized_name),
+        });
     }
 
-    pub async fn generate_daily_song(&self, genre: &GenreTypes) -> Option<SimplifiedTrack> {
-        let genre: String = genre.into();
-        let recommendations = match self.get_recommendations(genre, 1).await {
-            Ok(rec) => rec,
-            Err(_) => return None,
-        };
-        let song = match recommendations.tracks.first() {
-            Some(song) => song.to_owned(),
-            None => return None,
+    async fn top_seeds(&self, limit: u32) -> Result<Seeds, ProviderError> {
+        let artists = with_retry(|| self.client.current_user_top_artists_manual(Some(TimeRange::MediumTerm), Some(limit), None)).await?;
+        let tracks = with_retry(|| self.client.current_user_top_tracks_manual(Some(TimeRange::MediumTerm), Some(limit), None)).await?;
+
+        return Ok(Seeds {
+            artists: artists.items.iter().map(|artist| artist.id.id().to_string()).collect(),
+            tracks: tracks.items.iter().flat_map(|track| track.id.as_ref()).map(|id| id.id().to_string()).collect(),
+        });
+    }
+
+    async fn get_recommendations(&self, genre: &str, seeds: &Seeds, preferences: &Preferences, limit: u32) -> Result<Vec<Track>, ProviderError> {
+        let recommendations = self.recommendations(genre, seeds, preferences, limit).await?;
+        let ids: Vec<TrackId> = recommendations.tracks.into_iter().flat_map(|track| track.id).collect();
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Recommendations only carry simplified tracks, the album details come with the full ones.
+        let tracks = with_retry(|| self.client.tracks(ids.clone(), None)).await?;
+        return Ok(tracks.iter().flat_map(full_track).collect());
+    }
+
+    async fn audio_features(&self, track_id: &str) -> Result<AudioFeatures, ProviderError> {
+        let track_id = TrackId::from_id(track_id).map_err(ProviderError::from)?;
+        let features = with_retry(|| self.client.track_features(track_id.clone())).await?;
+
+        return Ok(AudioFeatures {
+            tempo: features.tempo,
+            key: features.key,
+            energy: features.energy,
+            valence: features.valence,
+            danceability: features.danceability,
+        });
+    }
+
+    async fn search_track(&self, artist: &str, title: &str) -> Result<Option<Track>, ProviderError> {
+        let query = format!("track:\"{}\" artist:\"{}\"", title.replace('"', ""), artist.replace('"', ""));
+        let result = with_retry(|| self.client.search(&query, SearchType::Track, None, None, Some(1), None)).await?;
+
+        let track = match result {
+            SearchResult::Tracks(page) => page.items.into_iter().next(),
+            _ => None,
         };
+        return Ok(track.as_ref().and_then(full_track));
+    }
 
-        return Some(song);
+    async fn create_playlist(&self, name: &str, description: &str) -> Result<String, ProviderError> {
+        let user_id = with_retry(|| self.client.current_user()).await?.id;
+        let playlist = with_retry(|| self.client.user_playlist_create(user_id.clone(), name, Some(false), Some(false), Some(description))).await?;
+        return Ok(playlist.id.id().to_string());
     }
 
-    pub async fn create_playlist(&self, day: &NaiveDate) -> Result<PlaylistId, ClientError> {
-        let user_id = self.client.current_user().await?.id;
-        let playlist = self.client.user_playlist_create(user_id, &format!("MusicApp Day {day}"), Some(false), Some(false), Some(&format!("MusicApp playlist for {day}"))).await?;
-        return Ok(playlist.id);
+    async fn add_songs_to_playlist(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        let playlist_id = PlaylistId::from_id(playlist_id).map_err(ProviderError::from)?;
+        with_retry(|| self.client.playlist_add_items(playlist_id.clone(), playable_ids(&songs), None)).await?;
+        return Ok(());
     }
 
-    pub async fn add_songs_to_playlist<'a>(&self, playlist_id: PlaylistId<'a>, songs: Vec<String>) -> Result<(), ClientError> {
-        let uris: Vec<PlayableId> = songs.iter().flat_map(|song| {
-            let track_id = match TrackId::from_uri(song) {
-                Ok(t) => { Some(PlayableId::Track(t)) }
-                Err(_) => { None }
-            };
-            return track_id;
-        }).collect();
-        self.client.playlist_add_items(playlist_id, uris, None).await?;
+    async fn replace_playlist_items(&self, playlist_id: &str, songs: Vec<String>) -> Result<(), ProviderError> {
+        let playlist_id = PlaylistId::from_id(playlist_id).map_err(ProviderError::from)?;
+        with_retry(|| self.client.playlist_replace_items(playlist_id.clone(), playable_ids(&songs))).await?;
         return Ok(());
     }
+}
+
+/// How many times a failed Spotify call is retried before giving up.
+const MAX_RETRIES: u32 = 4;
+/// Backoff for the first retry, doubled on every following one.
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+/// Longer `Retry-After` waits are treated as an exhausted quota instead of being slept through.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+enum Retry {
+    RateLimited(Option<Duration>),
+    Transient,
+    Never,
+}
+
+/// Runs a Spotify call, honoring `Retry-After` on 429s and retrying transient failures with jittered backoff.
+async fn with_retry<T, F, Fut>(mut call: F) -> Result<T, ProviderError>
+    where F: FnMut() -> Fut, Fut: Future<Output=Result<T, ClientError>> {
+    let mut attempt = 0;
+    loop {
+        let err = match call().await {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+
+        let wait = match classify(&err) {
+            Retry::RateLimited(retry_after) => {
+                let too_long = retry_after.map_or(false, |retry_after| retry_after > MAX_RETRY_AFTER);
+                if attempt >= MAX_RETRIES || too_long {
+                    return Err(ProviderError::QuotaExhausted { retry_after });
+                }
+                retry_after.unwrap_or_else(|| backoff(attempt))
+            }
+            Retry::Transient if attempt < MAX_RETRIES => backoff(attempt),
+            _ => return Err(ProviderError::Client(err)),
+        };
+
+        println!("Retrying Spotify call in {}ms: {}", wait.as_millis(), err);
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+fn classify(err: &ClientError) -> Retry {
+    let http = match err {
+        ClientError::Http(http) => http.as_ref(),
+        _ => return Retry::Never,
+    };
+
+    return match http {
+        HttpError::StatusCode(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = response.headers()
+                .get("Retry-After")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            Retry::RateLimited(retry_after)
+        }
+        HttpError::StatusCode(response) if response.status().is_server_error() => Retry::Transient,
+        HttpError::Client(err) if err.is_timeout() || err.is_connect() => Retry::Transient,
+        _ => Retry::Never,
+    };
+}
+
+/// Exponential backoff with up to 50% random jitter so concurrent requests don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let base = BASE_BACKOFF * 2u32.pow(attempt);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.subsec_nanos()).unwrap_or_default();
+    let jitter = base.mul_f64((nanos % 1000) as f64 / 2000.0);
+    return base + jitter;
+}
+
+/// The string Spotify uses for a model enum such as `Country` or `SubscriptionLevel`.
+fn serialized_name<T: serde::Serialize>(value: &T) -> Option<String> {
+    return match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    };
+}
+
+/// Maps a Spotify track to ours, skipping local tracks without an id, artist or link.
+fn full_track(track: &FullTrack) -> Option<Track> {
+    let track_id = track.id.as_ref()?;
+    let artist = track.artists.first()?.name.clone();
+    let link = track.external_urls.get("spotify")?.clone();
+
+    return Some(Track {
+        id: track_id.id().to_string(),
+        uri: track_id.uri(),
+        name: track.name.clone(),
+        artist,
+        artists: track.artists.iter().map(|artist| artist.name.clone()).collect(),
+        artist_ids: track.artists.iter().flat_map(|artist| artist.id.as_ref()).map(|id| id.id().to_string()).collect(),
+        link,
+        album: Some(track.album.name.clone()),
+        release_date: track.album.release_date.clone(),
+        album_cover: track.album.images.first().map(|image| image.url.clone()),
+        duration_ms: track.duration.num_milliseconds() as i32,
+        preview_url: track.preview_url.clone(),
+    });
+}
+
+/// Parses track ids or URIs into playable items, skipping anything that is not a track.
+fn playable_ids(songs: &[String]) -> Vec<PlayableId<'_>> {
+    return songs.iter().flat_map(|song| {
+        let track_id = match TrackId::from_id_or_uri(song) {
+            Ok(t) => { Some(PlayableId::Track(t)) }
+            Err(_) => { None }
+        };
+        return track_id;
+    }).collect();
+}
+
+/// Maps every preference the user has set to its Spotify recommendation attribute.
+fn recommendation_attributes(preferences: &Preferences) -> Vec<RecommendationsAttribute> {
+    let attributes = [
+        preferences.min_energy.map(RecommendationsAttribute::MinEnergy),
+        preferences.max_energy.map(RecommendationsAttribute::MaxEnergy),
+        preferences.target_energy.map(RecommendationsAttribute::TargetEnergy),
+        preferences.min_popularity.map(RecommendationsAttribute::MinPopularity),
+        preferences.max_popularity.map(RecommendationsAttribute::MaxPopularity),
+        preferences.target_popularity.map(RecommendationsAttribute::TargetPopularity),
+        preferences.min_valence.map(RecommendationsAttribute::MinValence),
+        preferences.max_valence.map(RecommendationsAttribute::MaxValence),
+        preferences.target_valence.map(RecommendationsAttribute::TargetValence),
+        preferences.min_danceability.map(RecommendationsAttribute::MinDanceability),
+        preferences.max_danceability.map(RecommendationsAttribute::MaxDanceability),
+        preferences.target_danceability.map(RecommendationsAttribute::TargetDanceability),
+        preferences.min_tempo.map(RecommendationsAttribute::MinTempo),
+        preferences.max_tempo.map(RecommendationsAttribute::MaxTempo),
+        preferences.target_tempo.map(RecommendationsAttribute::TargetTempo),
+        preferences.min_acousticness.map(RecommendationsAttribute::MinAcousticness),
+        preferences.max_acousticness.map(RecommendationsAttribute::MaxAcousticness),
+        preferences.target_acousticness.map(RecommendationsAttribute::TargetAcousticness),
+    ];
+
+    return attributes.into_iter().flatten().collect();
 }
\ No newline at end of file
@@ -1,18 +1,77 @@
-use bincode::Error;
-use poem::session::Session;
+use std::fmt;
+
+use chrono::{Duration, Utc};
 use rspotify::Token;
 
-/// Reads a serialized user token.
-pub fn read_token(session: &Session) -> Result<Token, Error> {
-    let serialized_token: Vec<u8> = session.get("access_token").unwrap_or_default();
-    let token = bincode::deserialize(&serialized_token[..])?;
+use crate::models::user::User;
+use crate::services::db::DB;
+use crate::services::provider::{ProviderError, ProviderKind};
 
-    return Ok(token);
+/// Tokens expiring within this many minutes are refreshed before being used.
+pub const REFRESH_MARGIN_MINUTES: i64 = 10;
+
+#[derive(Debug)]
+pub enum TokenError {
+    UserNotFound,
+    Database(sqlx::Error),
+    Provider(ProviderError),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TokenError::UserNotFound => write!(f, "no user found"),
+            TokenError::Database(err) => write!(f, "{}", err),
+            TokenError::Provider(err) => write!(f, "{}", err),
+        };
+    }
 }
 
-pub fn write_token(token: Token, session: &Session) -> Result<(), Error> {
-    let serialized_token = bincode::serialize(&token)?;
-    session.set("access_token", serialized_token);
+impl std::error::Error for TokenError {}
+
+impl From<sqlx::Error> for TokenError {
+    fn from(value: sqlx::Error) -> Self {
+        return TokenError::Database(value);
+    }
+}
 
-    return Ok(());
+impl From<ProviderError> for TokenError {
+    fn from(value: ProviderError) -> Self {
+        return TokenError::Provider(value);
+    }
+}
+
+/// Loads the user's current token from the database, refreshing it first if it is about to expire.
+pub async fn load_token(db: &DB, provider: &ProviderKind, user_id: i32) -> Result<Token, TokenError> {
+    let user = db.get_user(user_id).await?.ok_or(TokenError::UserNotFound)?;
+    let expires_soon = match user.expires_at {
+        Some(expires_at) => expires_at - Duration::minutes(REFRESH_MARGIN_MINUTES) < Utc::now(),
+        None => false,
+    };
+
+    if expires_soon {
+        return refresh_user_token(db, provider, &user).await;
+    }
+    return Ok(user.token());
+}
+
+/// Refreshes the user's token with the provider and persists the new access and refresh tokens.
+pub async fn refresh_user_token(db: &DB, provider: &ProviderKind, user: &User) -> Result<Token, TokenError> {
+    let mut token = match provider.refresh_token(user.token()).await {
+        Ok(token) => token,
+        Err(err) if err.is_revoked() => {
+            // Without a refresh token the worker stops picking the user up until they log in again.
+            db.clear_refresh_token(user.id).await?;
+            return Err(err.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if token.refresh_token.is_none() {
+        token.refresh_token = user.refresh_token.clone();
+    }
+
+    let expires_at = token.expires_at.unwrap_or_else(|| Utc::now() + token.expires_in);
+    db.update_user_token(user.id, &token.access_token, token.expires_in.num_seconds() as i32, &expires_at, &token.refresh_token.clone().unwrap_or_default()).await?;
+
+    return Ok(token);
 }
\ No newline at end of file
@@ -0,0 +1,33 @@
+use chrono::{Duration, Utc};
+
+use crate::services::db::DB;
+use crate::services::provider::ProviderKind;
+use crate::token::token::{REFRESH_MARGIN_MINUTES, refresh_user_token};
+
+/// How often the users table is scanned for tokens nearing expiry.
+const SCAN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+
+/// Keeps stored tokens fresh so requests never have to refresh them, runs until the server stops.
+pub async fn run(db: DB, provider: ProviderKind) {
+    let mut interval = tokio::time::interval(SCAN_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        // Anything expiring before the next scan plus the margin has to be refreshed now.
+        let deadline = Utc::now() + Duration::minutes(REFRESH_MARGIN_MINUTES) + Duration::from_std(SCAN_INTERVAL).unwrap_or_else(|_| Duration::zero());
+        let users = match db.get_users_expiring_before(&deadline).await {
+            Ok(users) => users,
+            Err(err) => {
+                println!("Failed to load users with expiring tokens: {:?}", err);
+                continue;
+            }
+        };
+
+        for user in users {
+            match refresh_user_token(&db, &provider, &user).await {
+                Ok(_) => println!("Refreshed token for user {}", user.id),
+                Err(err) => println!("Failed to refresh token for user {}: {}", user.id, err),
+            }
+        }
+    }
+}
\ No newline at end of file